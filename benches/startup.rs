@@ -0,0 +1,49 @@
+//! Compares startup with a cold and a warm application index.
+//!
+//! Runs the launcher binary with `--index-only`, which loads the index and exits before any
+//! window is created. Cold runs start from an empty `$XDG_CACHE_HOME`, warm runs reuse the index
+//! written by the previous run. Run with `cargo bench --bench startup`.
+
+use std::{
+    env, fs,
+    path::Path,
+    process::Command,
+    time::{Duration, Instant},
+};
+
+const RUNS: usize = 10;
+
+fn time_run(cache_home: &Path) -> Duration {
+    let start = Instant::now();
+    let status = Command::new(env!("CARGO_BIN_EXE_RustRocket"))
+        .arg("--index-only")
+        .env("XDG_CACHE_HOME", cache_home)
+        .status()
+        .expect("Failed to run launcher");
+    assert!(status.success(), "launcher exited with {}", status);
+    start.elapsed()
+}
+
+fn report(label: &str, mut samples: Vec<Duration>) {
+    samples.sort();
+    let mean = samples.iter().sum::<Duration>() / samples.len() as u32;
+    println!("{:<5} mean {:>9.2?}  min {:>9.2?}  median {:>9.2?}", label, mean, samples[0], samples[samples.len() / 2]);
+}
+
+fn main() {
+    let cache_home = env::temp_dir().join(format!("rustrocket-bench-{}", std::process::id()));
+
+    let cold = (0..RUNS)
+        .map(|_| {
+            let _ = fs::remove_dir_all(&cache_home);
+            time_run(&cache_home)
+        })
+        .collect();
+
+    // The last cold run left a fresh index behind
+    let warm = (0..RUNS).map(|_| time_run(&cache_home)).collect();
+
+    let _ = fs::remove_dir_all(&cache_home);
+    report("cold", cold);
+    report("warm", warm);
+}
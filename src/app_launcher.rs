@@ -1,166 +1,756 @@
 use std::{
-    fs,
     process::Command,
-    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Instant,
 };
-use xdg::BaseDirectories;
-use rayon::prelude::*;
-use crate::cache::{update_cache, RECENT_APPS_CACHE};
-use crate::gui_trait::AppInterface;
-
-fn get_desktop_entries() -> Vec<PathBuf> {
-    let xdg_dirs = BaseDirectories::new().unwrap();
-    let data_dirs = xdg_dirs.get_data_dirs();
-
-    data_dirs.par_iter()
-        .flat_map(|dir| {
-            let desktop_files = dir.join("applications");
-            fs::read_dir(&desktop_files).ok()
-                .into_iter()
-                .flat_map(|entries| entries.filter_map(Result::ok))
-                .map(|entry| entry.path())
-                .filter(|path| path.extension().map_or(false, |ext| ext == "desktop"))
-                .collect::<Vec<_>>()
-        })
-        .collect()
+use crate::applications::{Action, Application};
+use crate::cache::{rekey_cache, update_cache, RecentAppsCache, RECENT_APPS_CACHE};
+use crate::config::{config, config_error};
+use crate::fuzzy::{fuzzy_match, match_query, Match};
+use crate::gui_trait::{AppInterface, LauncherEvent, Movement, PowerPrompt, ResultRow, ViewModel};
+use crate::power::{PendingPower, PowerAction};
+use crate::modes::Mode;
+use crate::terminal::wrap_in_terminal;
+
+/// The field a query matched, in order of decreasing relevance.
+#[derive(Clone, Copy, PartialEq)]
+enum MatchField {
+    Name,
+    Keywords,
+    GenericName,
+    Categories,
+    Comment,
+}
+
+impl MatchField {
+    /// Added to the fuzzy score. Keywords and GenericName are equally relevant. A step is worth
+    /// about what one gap costs a match, so a contiguous keyword match still beats a name match
+    /// scattered over several words.
+    fn bonus(self) -> f64 {
+        match self {
+            MatchField::Name => 30.0,
+            MatchField::Keywords | MatchField::GenericName => 20.0,
+            MatchField::Categories => 10.0,
+            MatchField::Comment => 0.0,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            MatchField::Name => "name",
+            MatchField::Keywords => "keywords",
+            MatchField::GenericName => "generic name",
+            MatchField::Categories => "category",
+            MatchField::Comment => "comment",
+        }
+    }
 }
 
-fn parse_desktop_entry(path: &PathBuf) -> Option<(String, String)> {
-    let content = fs::read_to_string(path).ok()?;
-    let mut name = None;
-    let mut exec = None;
-    for line in content.lines() {
-        if line.starts_with("Name=") {
-            name = Some(line[5..].to_string());
-        } else if line.starts_with("Exec=") {
-            exec = Some(line[5..].to_string());
-        }
-        if name.is_some() && exec.is_some() {
-            break;
-        }
-    }
-    name.zip(exec).map(|(name, exec)| {
-        let cleaned_exec = exec.replace("%f", "")
-            .replace("%u", "")
-            .replace("%U", "")
-            .replace("%F", "")
-            .replace("%i", "")
-            .replace("%c", "")
-            .replace("%k", "")
-            .trim()
-            .to_string();
-        (name, cleaned_exec)
-    })
+impl Application {
+    /// The most relevant field matching `query`. Names, keywords and the generic name are
+    /// matched fuzzily; categories and comments need every word verbatim, since a long comment
+    /// contains almost any short subsequence. Positions are only kept for the displayed name.
+    fn search_match(&self, query: &str) -> Option<(MatchField, Match)> {
+        let verbatim = |text: &str| {
+            let text_lower = text.to_lowercase();
+            query.to_lowercase().split_whitespace().all(|word| text_lower.contains(word))
+                .then(|| match_query(query, text))
+                .flatten()
+        };
+        let best = |texts: &[String], matcher: &dyn Fn(&str) -> Option<Match>| {
+            texts.iter().filter_map(|text| matcher(text)).max_by_key(|found| found.score)
+        };
+        let without_positions = |found: Match| Match { positions: Vec::new(), ..found };
+
+        if let Some(found) = match_query(query, &self.name) {
+            return Some((MatchField::Name, found));
+        }
+        let fuzzy = |text: &str| match_query(query, text);
+        let found = match_query(query, &self.untranslated_name).map(|found| (MatchField::Name, found))
+            .or_else(|| best(&self.keywords, &fuzzy).map(|found| (MatchField::Keywords, found)))
+            .or_else(|| self.generic_name.as_deref().and_then(fuzzy).map(|found| (MatchField::GenericName, found)))
+            .or_else(|| best(&self.categories, &verbatim).map(|found| (MatchField::Categories, found)))
+            .or_else(|| self.comment.as_deref().and_then(verbatim).map(|found| (MatchField::Comment, found)));
+        found.map(|(field, found)| (field, without_positions(found)))
+    }
+
+    /// Every word of `query` has to match the application or action name, and at least one
+    /// has to start a word of the action name, so "firefox private" finds "New Private Window"
+    /// but neither "firefox" alone nor a stray letter lists every action. The match is reported
+    /// against `label`.
+    fn action_match(&self, action: &Action, query: &str, label: &str) -> Option<Match> {
+        let mut words = query.split_whitespace().peekable();
+        let matches = words.peek().is_some()
+            && query.split_whitespace().all(|word| fuzzy_match(word, &self.name).is_some() || fuzzy_match(word, &action.name).is_some())
+            && words.any(|word| starts_word(&action.name, word));
+        if matches {
+            match_query(query, label)
+        } else {
+            None
+        }
+    }
 }
 
-fn search_applications(query: &str, applications: &[(String, String)]) -> Vec<(String, String)> {
-    applications.iter()
-        .filter(|(name, _)| name.to_lowercase().contains(&query.to_lowercase()))
-        .cloned()
-        .take(5)
+/// Shorter prefixes are too common to single out an action.
+const MIN_ACTION_PREFIX: usize = 2;
+
+/// Whether `prefix` starts one of the words of `text`, ignoring case.
+fn starts_word(text: &str, prefix: &str) -> bool {
+    if prefix.chars().count() < MIN_ACTION_PREFIX {
+        return false;
+    }
+    let prefix = prefix.to_lowercase();
+    text.to_lowercase().split(|c: char| !c.is_alphanumeric()).any(|word| word.starts_with(&prefix))
+}
+
+/// A row in the result list: an application, or one of its actions.
+#[derive(Clone)]
+struct SearchResult {
+    app: Application,
+    action: Option<usize>,
+    /// Set for rows produced by a search rather than from history.
+    matched: Option<MatchField>,
+    /// Char indices of the label to highlight.
+    highlights: Vec<usize>,
+}
+
+impl SearchResult {
+    fn new(app: &Application) -> Self {
+        Self { app: app.clone(), action: None, matched: None, highlights: Vec::new() }
+    }
+
+    fn label(&self) -> String {
+        match self.action {
+            Some(index) => format!("{} › {}", self.app.name, self.app.actions[index].name),
+            None => self.app.name.clone(),
+        }
+    }
+}
+
+/// How much launch history weighs against the fuzzy score. Frecency is log-scaled: an app
+/// launched daily for a month has a frecency of about 24 and gains 10 * ln(25), about 32
+/// points, a little more than one well placed character (16 for the match, 12 as a prefix).
+const FRECENCY_WEIGHT: f64 = 10.0;
+
+/// Results are ordered by fuzzy match score plus a bonus for the field that matched, blended
+/// with the launch frecency. Between matches of similar quality, Name comes first, then
+/// Keywords/GenericName, Categories and Comment.
+fn search_applications(query: &str, applications: &[Application], history: &RecentAppsCache, limit: usize) -> Vec<SearchResult> {
+    let mut matches: Vec<(SearchResult, f64)> = Vec::new();
+    for app in applications {
+        if let Some((field, found)) = app.search_match(query) {
+            let result = SearchResult { app: app.clone(), action: None, matched: Some(field), highlights: found.positions };
+            matches.push((result, found.score as f64 + field.bonus() + FRECENCY_WEIGHT * history.frecency(&app.id).ln_1p()));
+        }
+        for (index, action) in app.actions.iter().enumerate() {
+            let mut result = SearchResult { app: app.clone(), action: Some(index), matched: Some(MatchField::Name), highlights: Vec::new() };
+            if let Some(found) = app.action_match(action, query, &result.label()) {
+                result.highlights = found.positions;
+                matches.push((result, found.score as f64 + MatchField::Name.bonus() + FRECENCY_WEIGHT * history.frecency(&app.id).ln_1p()));
+            }
+        }
+    }
+    matches.sort_by(|(_, a_score), (_, b_score)| b_score.total_cmp(a_score));
+    matches.into_iter()
+        .take(limit)
+        .map(|(result, _)| result)
         .collect()
 }
 
-fn launch_app(app_name: &str, exec_cmd: &str) -> Result<(), Box<dyn std::error::Error>> {
-    update_cache(app_name)?;
+/// Launches the application (or action) of `result`. Only desktop applications are recorded
+/// in the launch history; failing to record a launch does not stop it.
+fn launch_app(result: &SearchResult, record: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let app = &result.app;
+    if record {
+        if let Err(err) = update_cache(&app.id) {
+            eprintln!("Failed to record the launch of {}: {}", app.id, err);
+        }
+    }
 
     let home_dir = dirs::home_dir().ok_or("Failed to find home directory")?;
-    Command::new("sh")
-        .arg("-c")
-        .arg(exec_cmd)
-        .current_dir(home_dir)
-        .spawn()?;
+    let config = config();
+    let app_override = config.apps.get(&app.id);
+    let exec = match result.action {
+        Some(index) => &app.actions[index].exec,
+        None => &app.exec,
+    };
+    let mut argv: Vec<String> = app_override
+        .and_then(|app_override| app_override.prefix.as_deref())
+        .map(|prefix| prefix.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+    argv.extend_from_slice(exec);
+    if app.terminal {
+        argv = wrap_in_terminal(&argv)?;
+    }
+
+    // The config override wins over Path=, and anything that is not a directory falls back to $HOME
+    let working_dir = app_override
+        .and_then(|app_override| app_override.working_dir.as_ref())
+        .map(|dir| match dir.strip_prefix("~") {
+            Ok(relative) => home_dir.join(relative),
+            Err(_) => dir.clone(),
+        })
+        .or_else(|| app.working_dir.clone())
+        .filter(|dir| dir.is_dir())
+        .unwrap_or(home_dir);
+
+    let (program, args) = argv.split_first().ok_or("Empty command line")?;
+    let mut command = Command::new(program);
+    command.args(args).current_dir(working_dir);
+    if let Some(app_override) = app_override {
+        command.envs(&app_override.env);
+    }
+    let mut child = command.spawn()?;
+    // A daemon outlives what it launches, so reap children instead of leaving zombies
+    thread::spawn(move || child.wait());
     Ok(())
 }
 
+/// Launches the desktop application `id` as if it was picked in the launcher. Returns
+/// `Ok(false)` if there is no such application.
+pub fn launch_by_id(id: &str, applications: &[Application]) -> Result<bool, Box<dyn std::error::Error>> {
+    match applications.iter().find(|app| app.id == id) {
+        Some(app) => launch_app(&SearchResult::new(app), true).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+#[derive(Clone)]
+pub struct LauncherOptions {
+    pub mode: Mode,
+    /// Query the window opens with.
+    pub query: String,
+    /// Overrides `max_results` from the config.
+    pub max_results: Option<usize>,
+    /// Run power actions right away instead of counting down first.
+    pub no_confirm: bool,
+}
+
+/// Carries out a power action, `PowerAction::perform` outside of tests.
+type PowerExecutor = Arc<dyn Fn(PowerAction) -> Result<(), String> + Send + Sync>;
+
 pub struct AppLauncher {
     query: String,
-    applications: Vec<(String, String)>,
-    search_results: Vec<(String, String)>,
+    applications: Vec<Application>,
+    search_results: Vec<SearchResult>,
+    /// Application whose actions are shown as sub-results.
+    expanded: Option<String>,
+    /// Index of the selected row of `visible_results`.
+    selected: usize,
+    mode: Mode,
+    max_results: Option<usize>,
+    /// Set once an entry was picked, so callers can tell that from the window being dismissed.
+    launched: Arc<AtomicBool>,
+    pending_power: Option<PendingPower>,
+    power_executor: PowerExecutor,
+    /// Set while a power action is being performed, which polkit may ask a password for.
+    performing: bool,
+    /// Power actions this system supports, see `PowerAction::available`. Empty until they were
+    /// looked up in the background.
+    power_actions: Vec<PowerAction>,
+    /// Events sent by work running on other threads, handled on the next `Tick`.
+    background: (Sender<LauncherEvent>, Receiver<LauncherEvent>),
+    /// Background work whose event has not been handled yet.
+    in_flight: usize,
+    no_confirm: bool,
     is_quit: bool,
 }
 
-impl Default for AppLauncher {
-    fn default() -> Self {
-        let applications: Vec<(String, String)> = get_desktop_entries()
-            .par_iter()
-            .filter_map(|path| parse_desktop_entry(path))
-            .collect();
-
-        let recent_apps_cache = RECENT_APPS_CACHE.lock().expect("Failed to acquire read lock");
-
-        Self {
-            query: String::new(),
-            search_results: recent_apps_cache.recent_apps.iter().filter_map(|app_name| {
-                applications.iter().find(|(name, _)| name == app_name).cloned()
-            }).take(5).collect(),
+impl AppLauncher {
+    /// A launcher over already loaded entries of `options.mode`, as kept warm by the daemon.
+    pub fn new(applications: Vec<Application>, options: LauncherOptions) -> Self {
+        let mut launcher = Self {
+            query: options.query,
+            search_results: Vec::new(),
             applications,
+            expanded: None,
+            selected: 0,
+            mode: options.mode,
+            max_results: options.max_results,
+            launched: Arc::new(AtomicBool::new(false)),
+            pending_power: None,
+            power_executor: Arc::new(PowerAction::perform),
+            performing: false,
+            power_actions: Vec::new(),
+            background: mpsc::channel(),
+            in_flight: 0,
+            no_confirm: options.no_confirm,
             is_quit: false,
+        };
+        if launcher.mode == Mode::Drun {
+            // History migrated from the old name-keyed format gets keyed by desktop file ID. The
+            // old parser only read the untranslated `Name=`.
+            let keys: Vec<(&str, &str)> = launcher.applications.iter().map(|app| (app.untranslated_name.as_str(), app.id.as_str())).collect();
+            if let Err(err) = rekey_cache(&keys) {
+                eprintln!("Failed to migrate history: {}", err);
+            }
+        }
+        launcher.refresh();
+        // Asking logind can take a while, the window should not wait for it
+        launcher.find_power_actions();
+        launcher
+    }
+
+    fn find_power_actions(&mut self) {
+        self.in_background(|| LauncherEvent::PowerActionsFound(PowerAction::available()));
+    }
+
+    /// Runs `work` on another thread. The event it returns is handled on a later `Tick`.
+    fn in_background(&mut self, work: impl FnOnce() -> LauncherEvent + Send + 'static) {
+        let sender = self.background.0.clone();
+        self.in_flight += 1;
+        thread::spawn(move || {
+            // Only fails when the launcher is gone
+            let _ = sender.send(work());
+        });
+    }
+
+    /// Handles the events of background work that finished since the last call.
+    fn receive_background(&mut self) {
+        while let Ok(event) = self.background.1.try_recv() {
+            self.in_flight -= 1;
+            self.handle_event(event);
         }
     }
+
+    fn max_results(&self) -> usize {
+        self.max_results.unwrap_or_else(|| config().max_results)
+    }
+
+    /// Recomputes the results for the current query. Without a query, application launchers
+    /// list the most frecent applications and other modes their first entries.
+    fn refresh(&mut self) {
+        if !self.query.is_empty() {
+            let history = RECENT_APPS_CACHE.lock().expect("Failed to acquire read lock");
+            self.search_results = search_applications(&self.query, &self.applications, &history, self.max_results());
+        } else if self.mode == Mode::Drun {
+            let history = RECENT_APPS_CACHE.lock().expect("Failed to acquire read lock");
+            self.search_results = history.most_frecent().iter().filter_map(|app_id| {
+                self.applications.iter().find(|app| &app.id == app_id).map(SearchResult::new)
+            }).take(self.max_results()).collect();
+        } else {
+            self.search_results = self.applications.iter().take(self.max_results()).map(SearchResult::new).collect();
+        }
+    }
+
+    pub fn launched(&self) -> Arc<AtomicBool> {
+        self.launched.clone()
+    }
 }
 
 impl AppInterface for AppLauncher {
-    fn update(&mut self) {
-        if self.is_quit {
-            std::process::exit(0);
+    fn handle_event(&mut self, event: LauncherEvent) {
+        match event {
+            LauncherEvent::QueryChanged(query) => self.search(&query),
+            LauncherEvent::Submit if self.pending_power.is_some() => self.confirm_power(),
+            LauncherEvent::Submit => {
+                if let Some(result) = self.selected_result() {
+                    self.launch(&result);
+                }
+            }
+            LauncherEvent::Activate(row) => {
+                if let Some(result) = self.visible_results().get(row).cloned() {
+                    self.launch(&result);
+                }
+            }
+            LauncherEvent::Cancel if self.pending_power.is_some() => self.pending_power = None,
+            LauncherEvent::Cancel => self.is_quit = true,
+            LauncherEvent::MoveSelection(movement) => self.move_selection(movement),
+            LauncherEvent::ToggleActions(row) => self.toggle_actions(row),
+            LauncherEvent::PowerAction(action) => self.request_power(action, Instant::now()),
+            LauncherEvent::ConfirmPower => self.confirm_power(),
+            LauncherEvent::CancelPower => self.pending_power = None,
+            LauncherEvent::Tick => {
+                self.receive_background();
+                self.tick(Instant::now());
+            }
+            LauncherEvent::ConfigChanged => {
+                self.find_power_actions();
+                self.refresh();
+            }
+            LauncherEvent::PowerActionsFound(actions) => self.power_actions = actions,
+            LauncherEvent::PowerPerformed(result) => self.power_performed(result),
         }
     }
 
-    fn render(&self) -> String {
-        // This method is no longer needed for eframe implementation
-        String::new()
+    fn view(&self) -> ViewModel {
+        let rows = self.visible_results();
+        let results = rows.iter()
+            .map(|result| ResultRow {
+                label: result.label(),
+                highlights: result.highlights.clone(),
+                description: result.action.map_or_else(|| result.app.description(), |_| None),
+                matched_field: result.matched
+                    .filter(|field| *field != MatchField::Name)
+                    .map(|field| field.label().to_string()),
+                has_actions: result.action.is_none() && !result.app.actions.is_empty(),
+                expanded: result.action.is_none() && self.expanded.as_ref() == Some(&result.app.id),
+            })
+            .collect();
+        ViewModel {
+            query: self.query.clone(),
+            results,
+            selected: rows.len().checked_sub(1).map(|last| self.selected.min(last)),
+            time: crate::clock::get_current_time(),
+            config_error: config_error(),
+            power_prompt: self.pending_power.map(|pending| PowerPrompt {
+                action: pending.action,
+                seconds_left: pending.remaining(Instant::now()).as_secs_f64().ceil() as u64,
+            }),
+            power_actions: self.power_actions.clone(),
+            busy: self.in_flight > 0,
+            should_quit: self.is_quit,
+        }
     }
+}
 
-    fn handle_input(&mut self, input: &str) {
-        match input {
-            "ESC" => self.is_quit = true,
-            "ENTER" => self.launch_first_result(),
-            "P" => crate::power::power_off(),
-            "R" => crate::power::restart(),
-            "L" => crate::power::logout(),
-            _ => {
-                self.query = input.to_string();
-                self.search_results = search_applications(&self.query, &self.applications);
-            }
+impl AppLauncher {
+    fn search(&mut self, query: &str) {
+        self.query = query.to_string();
+        let history = RECENT_APPS_CACHE.lock().expect("Failed to acquire read lock");
+        self.search_results = search_applications(&self.query, &self.applications, &history, self.max_results());
+        self.expanded = None;
+        self.selected = 0;
+    }
+
+    /// The selected row. Results can shrink under the selection, e.g. on a config reload, so
+    /// the last row stands in for anything past the end.
+    fn selected_result(&self) -> Option<SearchResult> {
+        let rows = self.visible_results();
+        let index = self.selected.min(rows.len().checked_sub(1)?);
+        rows.into_iter().nth(index)
+    }
+
+    fn move_selection(&mut self, movement: Movement) {
+        let rows = self.visible_results().len();
+        if rows == 0 {
+            return;
         }
+        let page = self.max_results();
+        let current = self.selected.min(rows - 1);
+        self.selected = match movement {
+            Movement::Up => current.saturating_sub(1),
+            Movement::Down => current + 1,
+            Movement::PageUp => current.saturating_sub(page),
+            Movement::PageDown => current + page,
+            Movement::First => 0,
+            Movement::Last => rows - 1,
+        }
+        .min(rows - 1);
     }
 
-    fn should_quit(&self) -> bool {
-        self.is_quit
+    fn toggle_actions(&mut self, row: usize) {
+        let id = match self.visible_results().get(row) {
+            Some(result) if result.action.is_none() && !result.app.actions.is_empty() => result.app.id.clone(),
+            _ => return,
+        };
+        self.expanded = if self.expanded.as_ref() == Some(&id) { None } else { Some(id) };
+        // Keep the selection on the application; rows above it do not change
+        self.selected = row;
     }
 
-    fn get_query(&self) -> String {
-        self.query.clone()
+    /// Starts the countdown for `action`, or performs it right away if it does not ask for
+    /// confirmation. A second request replaces the pending one.
+    fn request_power(&mut self, action: PowerAction, now: Instant) {
+        if self.performing {
+            return;
+        }
+        let countdown = action.countdown();
+        if self.no_confirm || countdown.is_zero() {
+            self.perform_power(action);
+        } else {
+            self.pending_power = Some(PendingPower::new(action, countdown, now));
+        }
     }
 
-    fn get_search_results(&self) -> Vec<String> {
-        self.search_results.iter().map(|(name, _)| name.clone()).collect()
+    fn confirm_power(&mut self) {
+        if let Some(pending) = self.pending_power.take() {
+            self.perform_power(pending.action);
+        }
     }
 
-    fn get_time(&self) -> String {
-        crate::clock::get_current_time()
+    fn tick(&mut self, now: Instant) {
+        if self.pending_power.is_some_and(|pending| pending.is_due(now)) {
+            self.confirm_power();
+        }
     }
 
-    fn launch_app(&mut self, app_name: &str) {
-        if let Some((_, exec_cmd)) = self.search_results.iter().find(|(name, _)| name == app_name) {
-            if let Err(err) = launch_app(app_name, exec_cmd) {
-                eprintln!("Failed to launch app: {}", err);
-            } else {
+    /// Performs `action` in the background, so the window keeps drawing while polkit asks for
+    /// authorization.
+    fn perform_power(&mut self, action: PowerAction) {
+        let executor = self.power_executor.clone();
+        self.performing = true;
+        self.in_background(move || LauncherEvent::PowerPerformed(executor(action)));
+    }
+
+    fn power_performed(&mut self, result: Result<(), String>) {
+        self.performing = false;
+        match result {
+            Ok(()) => {
+                self.launched.store(true, Ordering::SeqCst);
                 self.is_quit = true;
             }
+            Err(err) => eprintln!("{}", err),
         }
     }
-}
 
-impl AppLauncher {
-    fn launch_first_result(&mut self) {
-        if let Some((app_name, exec_cmd)) = self.search_results.first() {
-            if let Err(err) = launch_app(app_name, exec_cmd) {
-                eprintln!("Failed to launch app: {}", err);
-            } else {
+    /// Does what picking `result` means in the current mode and closes the window on success.
+    fn launch(&mut self, result: &SearchResult) {
+        let launched = match self.mode {
+            Mode::Drun => launch_app(result, true),
+            Mode::Run => launch_app(result, false),
+            Mode::Dmenu => {
+                println!("{}", result.app.name);
+                Ok(())
+            }
+            Mode::Power => {
+                match PowerAction::from_id(&result.app.id) {
+                    Some(action) => self.request_power(action, Instant::now()),
+                    None => eprintln!("Unknown power action {}", result.app.id),
+                }
+                // Closing, if at all, is up to the confirmation
+                return;
+            }
+        };
+        match launched {
+            Ok(()) => {
+                self.launched.store(true, Ordering::SeqCst);
                 self.is_quit = true;
             }
+            Err(err) => eprintln!("Failed to launch app: {}", err),
         }
     }
-}
\ No newline at end of file
+
+    /// The search results with the actions of the expanded application inserted below it.
+    fn visible_results(&self) -> Vec<SearchResult> {
+        let mut rows = Vec::with_capacity(self.search_results.len());
+        for result in &self.search_results {
+            rows.push(result.clone());
+            if result.action.is_none() && self.expanded.as_ref() == Some(&result.app.id) {
+                rows.extend((0..result.app.actions.len()).map(|index| SearchResult { app: result.app.clone(), action: Some(index), matched: None, highlights: Vec::new() }));
+            }
+        }
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{path::PathBuf, sync::Mutex, time::Duration};
+    use crate::config::{reload_config, CONFIG_PATH};
+
+    fn app(id: &str, name: &str) -> Application {
+        Application { id: id.to_string(), name: name.to_string(), untranslated_name: name.to_string(), ..Application::default() }
+    }
+
+    fn with_actions(mut app: Application, actions: &[&str]) -> Application {
+        app.actions = actions.iter().map(|name| Action { name: name.to_string(), exec: vec!["true".to_string()] }).collect();
+        app
+    }
+
+    fn labels(query: &str, applications: &[Application]) -> Vec<String> {
+        search_applications(query, applications, &RecentAppsCache::default(), 10).iter().map(SearchResult::label).collect()
+    }
+
+    #[test]
+    fn actions_need_a_word_prefix() {
+        let applications = [
+            with_actions(app("firefox.desktop", "Firefox"), &["New Window", "New Private Window"]),
+            with_actions(app("files.desktop", "Files"), &["New Window"]),
+        ];
+        assert_eq!(labels("n", &applications), Vec::<String>::new());
+        assert_eq!(labels("firefox", &applications), ["Firefox"]);
+        assert_eq!(labels("firefox priv", &applications), ["Firefox › New Private Window"]);
+        // "wn" is a subsequence of "New Window" but starts none of its words
+        assert!(labels("files wn", &applications).is_empty());
+        assert_eq!(labels("files win", &applications), ["Files › New Window"]);
+    }
+
+    #[test]
+    fn tight_keyword_match_beats_scattered_name_match() {
+        let mut terminal = app("foot.desktop", "Foot");
+        terminal.keywords = vec!["shell".to_string(), "terminal".to_string()];
+        let applications = [app("thunderbird.desktop", "Thunderbird Mail"), terminal];
+        assert!(match_query("term", "Thunderbird Mail").is_some());
+        assert_eq!(labels("term", &applications), ["Foot", "Thunderbird Mail"]);
+    }
+
+    #[test]
+    fn name_beats_equally_good_keyword_match() {
+        let mut shell = app("shell.desktop", "Shell");
+        shell.keywords = vec!["terminal".to_string()];
+        let applications = [shell, app("terminal.desktop", "Terminal")];
+        assert_eq!(labels("terminal", &applications), ["Terminal", "Shell"]);
+    }
+
+    #[test]
+    fn scattered_name_match_beats_comment_match() {
+        let mut editor = app("editor.desktop", "Editor");
+        editor.comment = Some("Edit text in a terminal".to_string());
+        let applications = [editor, app("thunderbird.desktop", "Thunderbird Mail")];
+        assert_eq!(labels("term", &applications), ["Thunderbird Mail", "Editor"]);
+    }
+
+    /// Switches to `tests/fixtures/config.toml`, which poweroff counts down 10 s for and lock
+    /// does not.
+    fn use_test_config() {
+        CONFIG_PATH.get_or_init(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/config.toml"));
+        reload_config();
+    }
+
+    /// A launcher whose power actions are only recorded.
+    fn power_launcher(no_confirm: bool) -> (AppLauncher, Arc<Mutex<Vec<PowerAction>>>) {
+        use_test_config();
+        let options = LauncherOptions { mode: Mode::Dmenu, query: String::new(), max_results: None, no_confirm };
+        let mut launcher = AppLauncher::new(Vec::new(), options);
+        let performed = Arc::new(Mutex::new(Vec::new()));
+        let record = performed.clone();
+        launcher.power_executor = Arc::new(move |action| {
+            record.lock().unwrap().push(action);
+            Ok(())
+        });
+        (launcher, performed)
+    }
+
+    /// Sends `Tick` until all background work has been handled.
+    fn settle(launcher: &mut AppLauncher) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while launcher.view().busy {
+            assert!(Instant::now() < deadline, "background work did not finish");
+            thread::sleep(Duration::from_millis(10));
+            launcher.handle_event(LauncherEvent::Tick);
+        }
+    }
+
+    #[test]
+    fn power_actions_arrive_in_the_background() {
+        let (mut launcher, _) = power_launcher(false);
+        settle(&mut launcher);
+        assert_eq!(launcher.view().power_actions, PowerAction::available());
+    }
+
+    #[test]
+    fn power_request_waits_for_confirmation() {
+        let (mut launcher, performed) = power_launcher(false);
+        let now = Instant::now();
+        launcher.request_power(PowerAction::PowerOff, now);
+        assert_eq!(launcher.pending_power.map(|pending| pending.action), Some(PowerAction::PowerOff));
+        assert_eq!(launcher.pending_power.unwrap().remaining(now), Duration::from_secs(10));
+        assert!(performed.lock().unwrap().is_empty());
+        assert!(!launcher.view().should_quit);
+    }
+
+    #[test]
+    fn cancelling_clears_the_pending_action() {
+        for event in [LauncherEvent::Cancel, LauncherEvent::CancelPower] {
+            let (mut launcher, performed) = power_launcher(false);
+            launcher.request_power(PowerAction::PowerOff, Instant::now());
+            launcher.handle_event(event);
+            assert!(launcher.pending_power.is_none());
+            assert!(!launcher.view().should_quit, "cancelling the countdown must not close the window");
+            launcher.tick(Instant::now() + Duration::from_secs(60));
+            assert!(performed.lock().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn confirming_performs_once() {
+        for event in [LauncherEvent::Submit, LauncherEvent::ConfirmPower] {
+            let (mut launcher, performed) = power_launcher(false);
+            let now = Instant::now();
+            launcher.request_power(PowerAction::Reboot, now);
+            launcher.handle_event(event);
+            launcher.tick(now + Duration::from_secs(60));
+            launcher.handle_event(LauncherEvent::ConfirmPower);
+            settle(&mut launcher);
+            assert_eq!(*performed.lock().unwrap(), [PowerAction::Reboot]);
+            assert!(launcher.pending_power.is_none());
+            assert!(launcher.view().should_quit);
+            assert!(launcher.launched().load(Ordering::SeqCst));
+        }
+    }
+
+    #[test]
+    fn countdown_runs_out() {
+        let (mut launcher, performed) = power_launcher(false);
+        let now = Instant::now();
+        launcher.request_power(PowerAction::PowerOff, now);
+        launcher.tick(now + Duration::from_millis(9_999));
+        settle(&mut launcher);
+        assert!(performed.lock().unwrap().is_empty());
+        assert!(launcher.pending_power.is_some());
+        launcher.tick(now + Duration::from_secs(10));
+        settle(&mut launcher);
+        assert_eq!(*performed.lock().unwrap(), [PowerAction::PowerOff]);
+        launcher.tick(now + Duration::from_secs(11));
+        settle(&mut launcher);
+        assert_eq!(performed.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn no_confirm_and_zero_countdown_perform_right_away() {
+        let (mut launcher, performed) = power_launcher(true);
+        launcher.request_power(PowerAction::PowerOff, Instant::now());
+        settle(&mut launcher);
+        assert_eq!(*performed.lock().unwrap(), [PowerAction::PowerOff]);
+        assert!(launcher.pending_power.is_none());
+
+        let (mut launcher, performed) = power_launcher(false);
+        launcher.request_power(PowerAction::Lock, Instant::now());
+        settle(&mut launcher);
+        assert_eq!(*performed.lock().unwrap(), [PowerAction::Lock]);
+        assert!(launcher.pending_power.is_none());
+    }
+
+    #[test]
+    fn second_request_replaces_the_first() {
+        let (mut launcher, performed) = power_launcher(false);
+        let now = Instant::now();
+        launcher.request_power(PowerAction::PowerOff, now);
+        launcher.request_power(PowerAction::Reboot, now + Duration::from_secs(5));
+        launcher.tick(now + Duration::from_secs(10));
+        settle(&mut launcher);
+        assert!(performed.lock().unwrap().is_empty(), "the countdown restarts with the second request");
+        launcher.tick(now + Duration::from_secs(15));
+        settle(&mut launcher);
+        assert_eq!(*performed.lock().unwrap(), [PowerAction::Reboot]);
+    }
+
+    #[test]
+    fn failed_action_keeps_the_window_open() {
+        let (mut launcher, _) = power_launcher(true);
+        launcher.power_executor = Arc::new(|_| Err("no way".to_string()));
+        launcher.request_power(PowerAction::PowerOff, Instant::now());
+        settle(&mut launcher);
+        assert!(!launcher.view().should_quit);
+        assert!(!launcher.launched().load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn window_keeps_running_while_performing() {
+        let (mut launcher, _) = power_launcher(true);
+        let (release, wait) = mpsc::channel::<()>();
+        let wait = Mutex::new(wait);
+        launcher.power_executor = Arc::new(move |_| {
+            // Like polkit waiting for a password
+            let _ = wait.lock().unwrap().recv();
+            Ok(())
+        });
+        launcher.request_power(PowerAction::PowerOff, Instant::now());
+        launcher.handle_event(LauncherEvent::Tick);
+        assert!(launcher.view().busy);
+        assert!(!launcher.view().should_quit);
+        // Requests are ignored until the first one is done
+        launcher.request_power(PowerAction::Reboot, Instant::now());
+        assert!(launcher.pending_power.is_none());
+        release.send(()).unwrap();
+        settle(&mut launcher);
+        assert!(launcher.view().should_quit);
+    }
+}
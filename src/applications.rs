@@ -0,0 +1,219 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+use serde::{Serialize, Deserialize};
+use xdg::BaseDirectories;
+use once_cell::sync::Lazy;
+use crate::desktop_entry::{DesktopEntry, Group, Locale};
+use crate::exec::{self, find_executable, ExecContext};
+
+/// Set `RUSTROCKET_DEBUG=1` to log why desktop entries are left out of the application list.
+pub static DEBUG: Lazy<bool> = Lazy::new(|| env::var_os("RUSTROCKET_DEBUG").is_some_and(|value| !value.is_empty() && value != "0"));
+
+static CURRENT_DESKTOPS: Lazy<Vec<String>> = Lazy::new(|| {
+    env::var("XDG_CURRENT_DESKTOP")
+        .map(|value| value.split(':').filter(|desktop| !desktop.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default()
+});
+
+/// `applications` directories in order of decreasing precedence: `$XDG_DATA_HOME` first,
+/// then `$XDG_DATA_DIRS`.
+pub fn application_dirs() -> Vec<PathBuf> {
+    let xdg_dirs = BaseDirectories::new().unwrap();
+    std::iter::once(xdg_dirs.get_data_home())
+        .chain(xdg_dirs.get_data_dirs())
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+/// `<root>/kde/foo.desktop` has the desktop file ID `kde-foo.desktop`.
+pub fn desktop_file_id(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let components: Option<Vec<&str>> = relative.components()
+        .map(|component| component.as_os_str().to_str())
+        .collect();
+    Some(components?.join("-"))
+}
+
+static LOCALE: Lazy<Option<Locale>> = Lazy::new(Locale::from_env);
+
+/// A `[Desktop Action <id>]` listed in the entry's `Actions` key.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Action {
+    pub name: String,
+    pub exec: Vec<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Application {
+    pub id: String,
+    pub name: String,
+    pub untranslated_name: String,
+    pub generic_name: Option<String>,
+    pub comment: Option<String>,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+    pub exec: Vec<String>,
+    pub terminal: bool,
+    pub working_dir: Option<PathBuf>,
+    pub actions: Vec<Action>,
+    /// `TryExec`, see `is_installed`.
+    pub try_exec: Option<String>,
+}
+
+impl Application {
+    pub fn description(&self) -> Option<String> {
+        match (&self.generic_name, &self.comment) {
+            (Some(generic_name), Some(comment)) => Some(format!("{} - {}", generic_name, comment)),
+            (Some(text), None) | (None, Some(text)) => Some(text.clone()),
+            (None, None) => None,
+        }
+    }
+
+    /// Whether the `TryExec` program, if any, is installed. Checked whenever applications are
+    /// loaded rather than when parsing, so the index does not keep hiding an application after
+    /// its program gets installed.
+    pub fn is_installed(&self) -> bool {
+        match &self.try_exec {
+            Some(try_exec) if find_executable(try_exec).is_none() => {
+                if *DEBUG {
+                    eprintln!("Excluding {}: TryExec={} is not installed", self.id, try_exec);
+                }
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Returns why the spec says `group` must not be listed on `desktops`, the current desktops, if
+/// it must not. `TryExec` is left to `Application::is_installed`.
+fn exclusion_reason(group: &Group, desktops: &[String]) -> Option<String> {
+    match group.raw("Type") {
+        Some("Application") => {}
+        Some(other) => return Some(format!("Type={} is not Application", other)),
+        None => return Some("missing Type key".to_string()),
+    }
+    if group.boolean("Hidden") == Some(true) {
+        return Some("Hidden=true".to_string());
+    }
+    if group.boolean("NoDisplay") == Some(true) {
+        return Some("NoDisplay=true".to_string());
+    }
+    if let Some(only_show_in) = group.strings("OnlyShowIn") {
+        if !only_show_in.iter().any(|desktop| desktops.contains(desktop)) {
+            return Some(format!(
+                "OnlyShowIn={} does not match XDG_CURRENT_DESKTOP={}",
+                only_show_in.join(";"),
+                desktops.join(":")
+            ));
+        }
+    }
+    if let Some(not_show_in) = group.strings("NotShowIn") {
+        if let Some(desktop) = not_show_in.iter().find(|desktop| desktops.contains(desktop)) {
+            return Some(format!("NotShowIn contains {}", desktop));
+        }
+    }
+    None
+}
+
+fn parse_action(entry: &DesktopEntry, id: &str, context: &ExecContext) -> Option<Action> {
+    let group = entry.group(&format!("Desktop Action {}", id))?;
+    let name = group.locale_string("Name", LOCALE.as_ref())?;
+    let icon = group.string("Icon");
+    let context = ExecContext { icon: icon.as_deref().or(context.icon), ..*context };
+    let exec = exec::expand(&group.string("Exec")?, &context).ok()?;
+    Some(Action { name, exec })
+}
+
+pub fn parse_desktop_entry(id: &str, path: &Path) -> Option<Application> {
+    let entry = match DesktopEntry::from_path(path) {
+        Ok(entry) => entry,
+        Err(err) => {
+            eprintln!("Skipping {}: {}", path.display(), err);
+            return None;
+        }
+    };
+    let group = entry.main_group();
+    if let Some(reason) = exclusion_reason(group, &CURRENT_DESKTOPS) {
+        if *DEBUG {
+            eprintln!("Excluding {}: {}", path.display(), reason);
+        }
+        return None;
+    }
+    let locale = LOCALE.as_ref();
+    let untranslated_name = group.string("Name")?;
+    let name = group.locale_string("Name", locale).unwrap_or_else(|| untranslated_name.clone());
+    let icon = group.string("Icon");
+    let context = ExecContext { icon: icon.as_deref(), name: &name, desktop_file: path };
+    let exec = match exec::expand(&group.string("Exec")?, &context) {
+        Ok(argv) => argv,
+        Err(err) => {
+            eprintln!("Skipping {}: {}", path.display(), err);
+            return None;
+        }
+    };
+    let actions = group.strings("Actions").unwrap_or_default()
+        .iter()
+        .filter_map(|action_id| parse_action(&entry, action_id, &context))
+        .collect();
+    Some(Application {
+        id: id.to_string(),
+        name,
+        untranslated_name,
+        generic_name: group.locale_string("GenericName", locale),
+        comment: group.locale_string("Comment", locale),
+        keywords: group.locale_strings("Keywords", locale).unwrap_or_default(),
+        categories: group.strings("Categories").unwrap_or_default(),
+        exec,
+        terminal: group.boolean("Terminal").unwrap_or(false),
+        working_dir: group.string("Path").filter(|path| !path.is_empty()).map(PathBuf::from),
+        actions,
+        try_exec: group.string("TryExec").filter(|try_exec| !try_exec.is_empty()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Option<Application> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/desktop").join(name);
+        parse_desktop_entry(name, &path)
+    }
+
+    #[test]
+    fn try_exec_is_checked_after_parsing() {
+        let missing = fixture("try_exec_missing.desktop").expect("TryExec must not exclude while parsing");
+        assert_eq!(missing.try_exec.as_deref(), Some("rustrocket-missing-program"));
+        assert!(!missing.is_installed());
+        assert!(fixture("try_exec_installed.desktop").unwrap().is_installed());
+        assert!(fixture("browser.desktop").unwrap().is_installed());
+    }
+
+    /// Why an entry with the `[Desktop Entry]` lines `keys` is left out on `desktops`.
+    fn exclusion(keys: &str, desktops: &[&str]) -> Option<String> {
+        let entry = DesktopEntry::parse(&format!("[Desktop Entry]\nName=Foo\nExec=foo\n{}", keys)).unwrap();
+        let desktops: Vec<String> = desktops.iter().map(|desktop| desktop.to_string()).collect();
+        exclusion_reason(entry.main_group(), &desktops)
+    }
+
+    #[test]
+    fn exclusion_rules() {
+        assert_eq!(exclusion("Type=Application\n", &[]), None);
+        assert_eq!(exclusion("", &[]).as_deref(), Some("missing Type key"));
+        assert_eq!(exclusion("Type=Link\n", &[]).as_deref(), Some("Type=Link is not Application"));
+        assert_eq!(exclusion("Type=Application\nHidden=true\n", &[]).as_deref(), Some("Hidden=true"));
+        assert_eq!(exclusion("Type=Application\nNoDisplay=true\n", &[]).as_deref(), Some("NoDisplay=true"));
+        assert_eq!(exclusion("Type=Application\nNoDisplay=false\n", &[]), None);
+
+        let only_kde = "Type=Application\nOnlyShowIn=KDE;\n";
+        assert_eq!(exclusion(only_kde, &["GNOME"]).as_deref(), Some("OnlyShowIn=KDE does not match XDG_CURRENT_DESKTOP=GNOME"));
+        assert_eq!(exclusion(only_kde, &["ubuntu", "KDE"]), None);
+
+        let not_gnome = "Type=Application\nNotShowIn=GNOME;Unity;\n";
+        assert_eq!(exclusion(not_gnome, &["Unity"]).as_deref(), Some("NotShowIn contains Unity"));
+        assert_eq!(exclusion(not_gnome, &["sway"]), None);
+    }
+}
@@ -1,43 +1,444 @@
 use std::sync::Mutex;
-use std::collections::VecDeque;
-use std::fs;
-use std::path::PathBuf;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
-use bincode::{serialize, deserialize};
+use bincode::Options;
 use once_cell::sync::Lazy;
+use xdg::BaseDirectories;
+use crate::config::config;
 
-static RECENT_APPS_FILE: Lazy<PathBuf> = Lazy::new(|| PathBuf::from("recent_apps.bin"));
+/// Where the history used to live: relative to whatever directory we were started from.
+const LEGACY_RECENT_APPS_FILE: &str = "recent_apps.bin";
 
-#[derive(Serialize, Deserialize, Clone)]
+pub static XDG_DIRS: Lazy<BaseDirectories> = Lazy::new(|| {
+    BaseDirectories::with_prefix("rustrocket").expect("Failed to resolve XDG base directories")
+});
+
+/// `$XDG_STATE_HOME/rustrocket/recent_apps.bin`
+static RECENT_APPS_FILE: Lazy<PathBuf> = Lazy::new(|| XDG_DIRS.get_state_home().join("recent_apps.bin"));
+
+/// Every cache file starts with this magic followed by the little-endian `u32` format version.
+const CACHE_MAGIC: &[u8; 4] = b"RRKT";
+/// Version 0 was a bare `VecDeque<String>` of names, version 1 the headerless frecency map.
+const CACHE_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct AppUsage {
+    pub launch_count: u32,
+    /// Unix timestamps of the latest launches, newest first.
+    pub recent_launches: VecDeque<i64>,
+}
+
+impl AppUsage {
+    /// The total launch count weighted by how recent the sampled launches are, so an app
+    /// launched daily keeps a high score while one used a lot months ago fades out.
+    pub fn frecency(&self, now: i64) -> f64 {
+        if self.recent_launches.is_empty() {
+            return 0.0;
+        }
+        let half_life_days = config().history.half_life_days;
+        let weight_sum: f64 = self.recent_launches.iter()
+            .map(|&launched| {
+                let age_days = (now - launched).max(0) as f64 / 86_400.0;
+                0.5f64.powf(age_days / half_life_days)
+            })
+            .sum();
+        f64::from(self.launch_count) * weight_sum / self.recent_launches.len() as f64
+    }
+}
+
+/// Launch history keyed by desktop file ID.
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct RecentAppsCache {
-    pub recent_apps: VecDeque<String>,
+    pub apps: HashMap<String, AppUsage>,
+}
+
+impl RecentAppsCache {
+    pub fn frecency(&self, app_id: &str) -> f64 {
+        let now = chrono::Utc::now().timestamp();
+        self.apps.get(app_id).map_or(0.0, |usage| usage.frecency(now))
+    }
+
+    /// IDs of the most frecent applications, best first.
+    pub fn most_frecent(&self) -> Vec<String> {
+        let now = chrono::Utc::now().timestamp();
+        let mut ranked: Vec<(&String, f64)> = self.apps.iter()
+            .map(|(id, usage)| (id, usage.frecency(now)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked.into_iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    fn needs_rekey(&self, old_key: &str, new_key: &str) -> bool {
+        old_key != new_key && self.apps.contains_key(old_key) && !self.apps.contains_key(new_key)
+    }
+
+    /// Moves history recorded under `old_key` (e.g. a name from the legacy format) to `new_key`.
+    /// Returns whether anything moved.
+    fn rekey(&mut self, old_key: &str, new_key: &str) -> bool {
+        if !self.needs_rekey(old_key, new_key) {
+            return false;
+        }
+        if let Some(usage) = self.apps.remove(old_key) {
+            self.apps.insert(new_key.to_string(), usage);
+        }
+        true
+    }
+
+    fn record_launch(&mut self, app_id: &str, now: i64) {
+        let usage = self.apps.entry(app_id.to_string()).or_default();
+        usage.launch_count += 1;
+        usage.recent_launches.push_front(now);
+        let history = &config().history;
+        usage.recent_launches.truncate(history.launches_per_app);
+
+        let max_apps = history.max_apps;
+        if self.apps.len() > max_apps {
+            let keep: Vec<String> = self.most_frecent().into_iter().take(max_apps).collect();
+            self.apps.retain(|id, _| keep.contains(id));
+        }
+    }
+
+    /// The previous format was a bare list of recently launched names, newest first. Those
+    /// become one launch each, a second apart to keep their order.
+    fn from_legacy(recent_apps: VecDeque<String>) -> Self {
+        let now = chrono::Utc::now().timestamp();
+        let apps = recent_apps.into_iter()
+            .enumerate()
+            .map(|(age, name)| (name, AppUsage { launch_count: 1, recent_launches: VecDeque::from(vec![now - age as i64]) }))
+            .collect();
+        Self { apps }
+    }
 }
 
-fn save_cache<T: Serialize>(file: &PathBuf, cache: &T) -> Result<(), Box<dyn std::error::Error>> {
-    let data = serialize(cache)?;
-    fs::write(file, data)?;
+/// The encoding `bincode::serialize` uses, but refusing trailing bytes so that probing the
+/// headerless legacy formats cannot silently accept a prefix of the wrong one.
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new().with_fixint_encoding().reject_trailing_bytes()
+}
+
+fn encode_cache(cache: &RecentAppsCache) -> Result<Vec<u8>, bincode::Error> {
+    let mut data = CACHE_MAGIC.to_vec();
+    data.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+    data.extend(bincode_options().serialize(cache)?);
+    Ok(data)
+}
+
+enum DecodeError {
+    /// Written by a newer release, e.g. before a downgrade. Valid, just not for us.
+    Newer(u32),
+    Corrupt(String),
+}
+
+/// Brings a payload of format `version` up to the current one.
+fn migrate(version: u32, payload: &[u8]) -> Result<RecentAppsCache, DecodeError> {
+    let corrupt = |e: bincode::Error| DecodeError::Corrupt(e.to_string());
+    match version {
+        0 => bincode_options().deserialize::<VecDeque<String>>(payload)
+            .map(RecentAppsCache::from_legacy)
+            .map_err(corrupt),
+        1 | CACHE_VERSION => bincode_options().deserialize(payload).map_err(corrupt),
+        newer => Err(DecodeError::Newer(newer)),
+    }
+}
+
+fn decode_cache(data: &[u8]) -> Result<RecentAppsCache, DecodeError> {
+    match data.strip_prefix(&CACHE_MAGIC[..]) {
+        Some(rest) if rest.len() >= 4 => {
+            let (version, payload) = rest.split_at(4);
+            migrate(u32::from_le_bytes([version[0], version[1], version[2], version[3]]), payload)
+        }
+        Some(_) => Err(DecodeError::Corrupt("truncated header".to_string())),
+        // Files written before the header existed
+        None => migrate(1, data).or_else(|_| migrate(0, data)),
+    }
+}
+
+/// `file` with `suffix` appended to its name.
+fn sibling(file: &Path, suffix: &str) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Takes an exclusive advisory lock shared by every launcher instance, released when the returned
+/// file is dropped. It lives next to `file` rather than on it, since `file` is replaced by rename.
+fn lock_cache(file: &Path) -> io::Result<File> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let lock = OpenOptions::new().create(true).truncate(false).write(true).open(sibling(file, ".lock"))?;
+    lock.lock()?;
+    Ok(lock)
+}
+
+/// Writes to a temporary file in the same directory and renames it over `file`, so readers and
+/// crashed writers never see a partially written file.
+pub fn write_atomically(file: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = sibling(file, &format!(".tmp.{}", std::process::id()));
+    let result = (|| {
+        let mut temp_file = File::create(&temp)?;
+        temp_file.write_all(data)?;
+        temp_file.sync_all()?;
+        fs::rename(&temp, file)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn save_cache(file: &Path, cache: &RecentAppsCache) -> Result<(), Box<dyn std::error::Error>> {
+    write_atomically(file, &encode_cache(cache)?)?;
+    Ok(())
+}
+
+/// Moves an undecodable cache out of the way to `<file>.corrupt` so the next start does not
+/// trip over it again, keeping it around for inspection. Earlier ones are kept too, as
+/// `<file>.corrupt.1` and so on.
+fn quarantine(file: &Path) -> Result<PathBuf, io::Error> {
+    let corrupt = (0..)
+        .map(|n| if n == 0 { sibling(file, ".corrupt") } else { sibling(file, &format!(".corrupt.{}", n)) })
+        .find(|candidate| !candidate.exists())
+        .expect("ran out of names for corrupt files");
+    fs::rename(file, &corrupt)?;
+    Ok(corrupt)
+}
+
+/// `None` when `file` was written by a newer release or could not be read. It is left alone,
+/// and must not be overwritten either.
+fn load_cache(file: &Path) -> Option<RecentAppsCache> {
+    let data = match fs::read(file) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Some(RecentAppsCache::default()),
+        Err(err) => {
+            eprintln!("Warning: failed to read {}: {}; launches are not recorded", file.display(), err);
+            return None;
+        }
+    };
+    match decode_cache(&data) {
+        Ok(cache) => Some(cache),
+        Err(DecodeError::Newer(version)) => {
+            eprintln!("Warning: {} was written by a newer RustRocket (format version {}); launches are not recorded", file.display(), version);
+            None
+        }
+        Err(DecodeError::Corrupt(err)) => {
+            match quarantine(file) {
+                Ok(corrupt) => eprintln!("Warning: {} is unreadable ({}), moved it to {}; starting with empty history", file.display(), err, corrupt.display()),
+                Err(rename_err) => eprintln!("Warning: {} is unreadable ({}) and could not be moved aside ({}); starting with empty history", file.display(), err, rename_err),
+            }
+            Some(RecentAppsCache::default())
+        }
+    }
+}
+
+/// Moves a `recent_apps.bin` left in the working directory by older versions to `target`,
+/// unless `target` already exists.
+fn migrate_legacy_file(target: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let legacy = Path::new(LEGACY_RECENT_APPS_FILE);
+    if target.exists() || !legacy.is_file() {
+        return Ok(());
+    }
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // rename fails across filesystems, e.g. from $HOME to a tmpfs state dir
+    if fs::rename(legacy, target).is_err() {
+        fs::copy(legacy, target)?;
+        fs::remove_file(legacy)?;
+    }
     Ok(())
 }
 
 pub static RECENT_APPS_CACHE: Lazy<Mutex<RecentAppsCache>> = Lazy::new(|| {
-    if RECENT_APPS_FILE.exists() {
-        let data = fs::read(&*RECENT_APPS_FILE).expect("Failed to read recent apps file");
-        let recent_apps: VecDeque<String> = deserialize(&data).expect("Failed to deserialize recent apps data");
-        Mutex::new(RecentAppsCache { recent_apps })
-    } else {
-        Mutex::new(RecentAppsCache { recent_apps: VecDeque::new() })
+    // Held while loading since the legacy file and unreadable files get moved around
+    let _lock = lock_cache(&RECENT_APPS_FILE)
+        .map_err(|err| eprintln!("Warning: failed to lock {}: {}", RECENT_APPS_FILE.display(), err));
+    if let Err(err) = migrate_legacy_file(&RECENT_APPS_FILE) {
+        eprintln!("Failed to migrate {}: {}", LEGACY_RECENT_APPS_FILE, err);
     }
+    Mutex::new(load_cache(&RECENT_APPS_FILE).unwrap_or_default())
 });
 
-pub fn update_cache(app_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// Applies `change` to the history in `file` and writes it back if `change` returns true.
+/// Other instances may have recorded launches since we loaded the history, so under the file
+/// lock the current file is re-read and changed rather than our copy written out. Returns the
+/// result, or `None` when the file is from a newer release and was left untouched.
+fn modify_cache_file<F>(file: &Path, change: F) -> Result<Option<RecentAppsCache>, Box<dyn std::error::Error>>
+where
+    F: FnOnce(&mut RecentAppsCache) -> bool,
+{
+    let _lock = lock_cache(file)?;
+    let mut merged = match load_cache(file) {
+        Some(merged) => merged,
+        None => return Ok(None),
+    };
+    if change(&mut merged) {
+        save_cache(file, &merged)?;
+    }
+    Ok(Some(merged))
+}
+
+/// `modify_cache_file` on our history file, refreshing the in-memory copy.
+fn modify_cache<F>(change: F) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnOnce(&mut RecentAppsCache) -> bool,
+{
     let mut cache = RECENT_APPS_CACHE.lock().map_err(|e| format!("Lock error: {:?}", e))?;
-    cache.recent_apps.retain(|x| x != app_name);
-    cache.recent_apps.push_front(app_name.to_string());
-    if cache.recent_apps.len() > 10 {
-        cache.recent_apps.pop_back();
-    }
-    let cache_data = cache.clone();
-    drop(cache);
-    save_cache(&RECENT_APPS_FILE, &cache_data)?;
+    if let Some(merged) = modify_cache_file(&RECENT_APPS_FILE, change)? {
+        *cache = merged;
+    }
     Ok(())
 }
+
+/// Records a launch of `app_id`.
+pub fn update_cache(app_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    modify_cache(|cache| {
+        cache.record_launch(app_id, chrono::Utc::now().timestamp());
+        true
+    })
+}
+
+/// Moves history recorded under each `(old_key, new_key)` pair's old key to its new one and
+/// saves the result, e.g. to key history from the legacy name-keyed format by desktop file ID.
+/// Does not touch the file unless our copy has such keys.
+pub fn rekey_cache(keys: &[(&str, &str)]) -> Result<(), Box<dyn std::error::Error>> {
+    let needed = {
+        let cache = RECENT_APPS_CACHE.lock().map_err(|e| format!("Lock error: {:?}", e))?;
+        keys.iter().any(|(old_key, new_key)| cache.needs_rekey(old_key, new_key))
+    };
+    if !needed {
+        return Ok(());
+    }
+    modify_cache(|cache| keys.iter().fold(false, |moved, (old_key, new_key)| cache.rekey(old_key, new_key) || moved))
+}
+
+/// Forgets every recorded launch.
+pub fn clear_history() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cache = RECENT_APPS_CACHE.lock().map_err(|e| format!("Lock error: {:?}", e))?;
+    let _lock = lock_cache(&RECENT_APPS_FILE)?;
+    save_cache(&RECENT_APPS_FILE, &RecentAppsCache::default())?;
+    *cache = RecentAppsCache::default();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for one test's files.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rustrocket-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn leaves_files_from_newer_versions_alone() {
+        let file = temp_dir("newer").join("recent_apps.bin");
+        let mut data = CACHE_MAGIC.to_vec();
+        data.extend_from_slice(&(CACHE_VERSION + 1).to_le_bytes());
+        data.extend_from_slice(b"whatever the future holds");
+        fs::write(&file, &data).unwrap();
+
+        assert!(load_cache(&file).is_none());
+        assert_eq!(fs::read(&file).unwrap(), data);
+        assert!(!sibling(&file, ".corrupt").exists());
+    }
+
+    #[test]
+    fn unreadable_files_are_not_overwritten() {
+        let file = temp_dir("unreadable").join("recent_apps.bin");
+        // Reading a directory fails with something other than NotFound
+        fs::create_dir(&file).unwrap();
+        let merged = modify_cache_file(&file, |cache| {
+            cache.record_launch("firefox.desktop", 0);
+            true
+        }).unwrap();
+        assert!(merged.is_none());
+        assert!(file.is_dir());
+    }
+
+    #[test]
+    fn quarantines_corrupt_files_without_replacing_earlier_ones() {
+        let file = temp_dir("corrupt").join("recent_apps.bin");
+        for content in ["first", "second", "third"] {
+            fs::write(&file, content).unwrap();
+            assert!(load_cache(&file).is_some_and(|cache| cache.apps.is_empty()));
+            assert!(!file.exists());
+        }
+        assert_eq!(fs::read_to_string(sibling(&file, ".corrupt")).unwrap(), "first");
+        assert_eq!(fs::read_to_string(sibling(&file, ".corrupt.1")).unwrap(), "second");
+        assert_eq!(fs::read_to_string(sibling(&file, ".corrupt.2")).unwrap(), "third");
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let file = temp_dir("roundtrip").join("recent_apps.bin");
+        let mut cache = RecentAppsCache::default();
+        cache.apps.insert("firefox.desktop".to_string(), AppUsage { launch_count: 3, recent_launches: VecDeque::from(vec![30, 20, 10]) });
+        save_cache(&file, &cache).unwrap();
+
+        let loaded = load_cache(&file).unwrap();
+        assert_eq!(loaded.apps["firefox.desktop"].launch_count, 3);
+        assert_eq!(loaded.apps["firefox.desktop"].recent_launches, [30, 20, 10]);
+    }
+
+    #[test]
+    fn concurrent_writers_lose_no_launches() {
+        const WRITERS: usize = 8;
+        const LAUNCHES: u32 = 25;
+        let file = temp_dir("race").join("recent_apps.bin");
+        let threads: Vec<_> = (0..WRITERS).map(|writer| {
+            let file = file.clone();
+            std::thread::spawn(move || {
+                for launch in 0..LAUNCHES {
+                    let app_id = format!("app{}.desktop", (writer + launch as usize) % 3);
+                    modify_cache_file(&file, |cache| {
+                        cache.record_launch(&app_id, i64::from(launch));
+                        true
+                    }).unwrap();
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let cache = decode_cache(&fs::read(&file).unwrap()).ok().expect("history no longer decodes");
+        let total: u32 = cache.apps.values().map(|usage| usage.launch_count).sum();
+        assert_eq!(total, WRITERS as u32 * LAUNCHES);
+        assert_eq!(cache.apps.len(), 3);
+    }
+
+    #[test]
+    fn rekeyed_legacy_history_survives_launches() {
+        let file = temp_dir("rekey").join("recent_apps.bin");
+        let legacy: VecDeque<String> = VecDeque::from(vec!["Firefox".to_string(), "Files".to_string()]);
+        fs::write(&file, bincode_options().serialize(&legacy).unwrap()).unwrap();
+
+        modify_cache_file(&file, |cache| cache.rekey("Firefox", "firefox.desktop")).unwrap();
+        modify_cache_file(&file, |cache| {
+            cache.record_launch("firefox.desktop", chrono::Utc::now().timestamp());
+            true
+        }).unwrap();
+
+        let cache = load_cache(&file).unwrap();
+        assert_eq!(cache.apps["firefox.desktop"].launch_count, 2);
+        assert!(!cache.apps.contains_key("Firefox"));
+        assert_eq!(cache.apps["Files"].launch_count, 1);
+    }
+
+    #[test]
+    fn only_writes_when_changed() {
+        let file = temp_dir("unchanged").join("recent_apps.bin");
+        assert!(modify_cache_file(&file, |cache| cache.rekey("Firefox", "firefox.desktop")).unwrap().is_some());
+        assert!(!file.exists());
+    }
+}
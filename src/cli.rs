@@ -0,0 +1,222 @@
+use std::{
+    error::Error,
+    io::{self, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use crate::app_launcher::{launch_by_id, LauncherOptions};
+use crate::cache::{clear_history, RECENT_APPS_CACHE};
+use crate::config::CONFIG_PATH;
+use crate::daemon;
+use crate::gui_trait::Backend;
+use crate::index::load_applications;
+use crate::ipc::Request;
+use crate::modes::Mode;
+use crate::power::PowerAction;
+
+/// Something was launched or picked, or a command succeeded.
+const EXIT_SUCCESS: u8 = 0;
+/// The window was closed without picking anything, like dmenu and rofi do.
+const EXIT_DISMISSED: u8 = 1;
+// clap exits with 2 on usage errors
+/// `launch` was given a desktop file ID that is not installed or not listed.
+const EXIT_UNKNOWN_APP: u8 = 3;
+/// Anything else went wrong.
+const EXIT_FAILURE: u8 = 4;
+
+const EXIT_CODES: &str = "Exit status:
+  0  an entry was picked or the command succeeded
+  1  the window was closed without picking anything
+  2  invalid arguments
+  3  `launch` was given an unknown desktop file ID
+  4  any other error";
+
+#[derive(Parser)]
+#[command(name = "RustRocket", version, about = "Application launcher for wlroots based Wayland compositors", after_help = EXIT_CODES)]
+struct Cli {
+    #[command(flatten)]
+    window: WindowArgs,
+
+    /// Read this configuration file instead of $XDG_CONFIG_HOME/rustrocket/config.toml
+    #[arg(short, long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Log why desktop entries are left out of the application list
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Stay resident and open the window when asked over the control socket
+    #[arg(long, group = "control")]
+    daemon: bool,
+
+    /// Open or focus the window of the running instance (the default)
+    #[arg(long, group = "control")]
+    show: bool,
+
+    /// Close the window of the running instance
+    #[arg(long, group = "control")]
+    hide: bool,
+
+    /// Open the window of the running instance, or close it if it is open
+    #[arg(long, group = "control")]
+    toggle: bool,
+
+    /// Load the application index and exit, used by benches/startup.rs
+    #[arg(long, hide = true, group = "control")]
+    index_only: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Args)]
+struct WindowArgs {
+    /// What to list
+    #[arg(short, long, value_enum, default_value_t = Mode::Drun)]
+    mode: Mode,
+
+    /// Open with this search query
+    #[arg(short, long, default_value = "")]
+    query: String,
+
+    /// Maximum number of results shown [default: `max_results` from the config, 5]
+    #[arg(short = 'n', long, value_parser = clap::value_parser!(u16).range(1..))]
+    max_results: Option<u16>,
+
+    /// Power off, restart and log out without a confirmation countdown
+    #[arg(long)]
+    no_confirm: bool,
+
+    /// Frontend that draws the window
+    #[arg(long, value_enum, default_value_t = Backend::Eframe)]
+    backend: Backend,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the listed desktop applications
+    ListApps {
+        #[arg(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Launch a desktop application by its desktop file ID, e.g. firefox.desktop
+    Launch {
+        desktop_id: String,
+    },
+    /// Run a power action right away, or list the ones available without an action
+    Power {
+        action: Option<PowerAction>,
+    },
+    /// Inspect or reset the launch history
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum HistoryCommand {
+    /// Print recorded launches, most frecent first
+    Show,
+    /// Forget all recorded launches
+    Clear,
+}
+
+impl WindowArgs {
+    fn options(&self) -> LauncherOptions {
+        LauncherOptions { mode: self.mode, query: self.query.clone(), max_results: self.max_results.map(usize::from), no_confirm: self.no_confirm }
+    }
+}
+
+/// Output is written with `writeln!` rather than `println!` so that piping into `head` is an
+/// error we report instead of a panic.
+fn list_apps(format: Format) -> Result<(), Box<dyn Error>> {
+    let applications = load_applications();
+    let mut out = io::stdout().lock();
+    match format {
+        Format::Json => writeln!(out, "{}", serde_json::to_string_pretty(&applications)?)?,
+        Format::Table => {
+            let id_width = applications.iter().map(|app| app.id.chars().count()).max().unwrap_or(0);
+            let name_width = applications.iter().map(|app| app.name.chars().count()).max().unwrap_or(0);
+            for app in &applications {
+                writeln!(out, "{:<id_width$}  {:<name_width$}  {}", app.id, app.name, app.exec.join(" "), id_width = id_width, name_width = name_width)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn power(action: Option<PowerAction>) -> Result<(), Box<dyn Error>> {
+    let available = PowerAction::available();
+    match action {
+        Some(action) if available.contains(&action) => Ok(action.perform()?),
+        Some(action) => Err(format!("{} is not available on this system", action.id()).into()),
+        None => {
+            let mut out = io::stdout().lock();
+            for action in available {
+                writeln!(out, "{:<24}{}", action.id(), action.label())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn show_history() -> Result<(), Box<dyn Error>> {
+    let history = RECENT_APPS_CACHE.lock().map_err(|e| format!("Lock error: {:?}", e))?;
+    let mut out = io::stdout().lock();
+    for id in history.most_frecent() {
+        writeln!(out, "{:>8.2}  {:>5}  {}", history.frecency(&id), history.apps[&id].launch_count, id)?;
+    }
+    Ok(())
+}
+
+fn run(cli: Cli) -> Result<u8, Box<dyn Error>> {
+    let Cli { window, command, .. } = cli;
+    let dismissed_unless = |launched: bool| if launched { EXIT_SUCCESS } else { EXIT_DISMISSED };
+    match command {
+        Some(Command::ListApps { format }) => list_apps(format).map(|()| EXIT_SUCCESS),
+        Some(Command::Launch { desktop_id }) => match launch_by_id(&desktop_id, &load_applications())? {
+            true => Ok(EXIT_SUCCESS),
+            false => {
+                eprintln!("No application with desktop file ID {}", desktop_id);
+                Ok(EXIT_UNKNOWN_APP)
+            }
+        },
+        Some(Command::Power { action }) => power(action).map(|()| EXIT_SUCCESS),
+        Some(Command::History { command: HistoryCommand::Show }) => show_history().map(|()| EXIT_SUCCESS),
+        Some(Command::History { command: HistoryCommand::Clear }) => clear_history().map(|()| EXIT_SUCCESS),
+        None if cli.daemon => daemon::run_daemon(window.options(), window.backend).map(|()| EXIT_SUCCESS),
+        None if cli.hide => daemon::hide().map(|()| EXIT_SUCCESS),
+        None if cli.index_only => {
+            load_applications();
+            Ok(EXIT_SUCCESS)
+        }
+        None if cli.toggle => daemon::show_or_run(Request::Toggle, window.options(), window.backend).map(dismissed_unless),
+        None => daemon::show_or_run(Request::Show, window.options(), window.backend).map(dismissed_unless),
+    }
+}
+
+pub fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if cli.verbose > 0 {
+        // Read lazily by the desktop entry parser, and inherited by a daemon we might start
+        std::env::set_var("RUSTROCKET_DEBUG", "1");
+    }
+    if let Some(path) = &cli.config {
+        let _ = CONFIG_PATH.set(path.clone());
+    }
+    match run(cli) {
+        Ok(code) => ExitCode::from(code),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use chrono::prelude::*;
 use std::time::SystemTime;
+use crate::config::config;
 
 pub fn get_current_time() -> String {
     let datetime: DateTime<Local> = SystemTime::now().into();
-    datetime.format("%I:%M %p %m/%d/%Y").to_string()
+    datetime.format(&config().clock.format).to_string()
 }
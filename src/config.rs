@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+};
+use serde::Deserialize;
+use once_cell::sync::{Lazy, OnceCell};
+use xdg::BaseDirectories;
+
+/// Settings read from `$XDG_CONFIG_HOME/rustrocket/config.toml`. Every key is optional and
+/// defaults to the built-in behavior.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Results listed at most.
+    pub max_results: usize,
+    /// Terminal used for `Terminal=true` entries, e.g. `"foot"` or `"wezterm start"`.
+    pub terminal: Option<String>,
+    pub window: WindowConfig,
+    pub theme: ThemeConfig,
+    pub clock: ClockConfig,
+    pub power: PowerConfig,
+    pub history: HistoryConfig,
+    /// Per-application launch overrides keyed by desktop file ID, e.g. `[apps."steam.desktop"]`.
+    pub apps: HashMap<String, AppOverride>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_results: 5,
+            terminal: None,
+            window: WindowConfig::default(),
+            theme: ThemeConfig::default(),
+            clock: ClockConfig::default(),
+            power: PowerConfig::default(),
+            history: HistoryConfig::default(),
+            apps: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self { width: 300.0, height: 400.0 }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// Dark or light colors; follows the system when unset.
+    pub dark: Option<bool>,
+    /// `#rrggbb` color for matched characters and selections.
+    pub accent: Option<String>,
+}
+
+impl ThemeConfig {
+    pub fn accent_rgb(&self) -> Option<[u8; 3]> {
+        self.accent.as_deref().and_then(parse_hex_color)
+    }
+}
+
+fn parse_hex_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii())?;
+    let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClockConfig {
+    /// A chrono `strftime` format.
+    pub format: String,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self { format: "%I:%M %p %m/%d/%Y".to_string() }
+    }
+}
+
+/// Commands tried in order for each power action, the first installed one is run. Each is
+/// the program followed by its arguments, e.g. `["systemctl", "poweroff"]`.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    /// Go through systemd-logind when it is reachable, keeping the commands as a fallback.
+    pub use_logind: bool,
+    pub poweroff: Vec<Vec<String>>,
+    pub reboot: Vec<Vec<String>>,
+    pub logout: Vec<Vec<String>>,
+    pub suspend: Vec<Vec<String>>,
+    pub hibernate: Vec<Vec<String>>,
+    pub hybrid_sleep: Vec<Vec<String>>,
+    pub suspend_then_hibernate: Vec<Vec<String>>,
+    pub lock: Vec<Vec<String>>,
+    pub countdown: PowerCountdown,
+}
+
+impl PowerConfig {
+    /// Every command list with its key.
+    fn command_lists(&self) -> [(&'static str, &Vec<Vec<String>>); 8] {
+        [
+            ("poweroff", &self.poweroff),
+            ("reboot", &self.reboot),
+            ("logout", &self.logout),
+            ("suspend", &self.suspend),
+            ("hibernate", &self.hibernate),
+            ("hybrid_sleep", &self.hybrid_sleep),
+            ("suspend_then_hibernate", &self.suspend_then_hibernate),
+            ("lock", &self.lock),
+        ]
+    }
+}
+
+/// Seconds an action waits for confirmation before it goes ahead on its own, as
+/// `[power.countdown]`. 0 skips the confirmation.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerCountdown {
+    pub poweroff: u64,
+    pub reboot: u64,
+    pub logout: u64,
+    pub suspend: u64,
+    pub hibernate: u64,
+    pub hybrid_sleep: u64,
+    pub suspend_then_hibernate: u64,
+    pub lock: u64,
+}
+
+/// Only the actions that end the session ask by default.
+impl Default for PowerCountdown {
+    fn default() -> Self {
+        Self {
+            poweroff: 10,
+            reboot: 10,
+            logout: 10,
+            suspend: 0,
+            hibernate: 0,
+            hybrid_sleep: 0,
+            suspend_then_hibernate: 0,
+            lock: 0,
+        }
+    }
+}
+
+fn commands(commands: &[&[&str]]) -> Vec<Vec<String>> {
+    commands.iter().map(|argv| argv.iter().map(|arg| arg.to_string()).collect()).collect()
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            use_logind: true,
+            poweroff: commands(&[&["shutdown", "-h", "now"], &["systemctl", "poweroff"], &["poweroff"], &["halt"]]),
+            reboot: commands(&[&["reboot"], &["systemctl", "reboot"], &["shutdown", "-r", "now"]]),
+            logout: commands(&[&["swaymsg", "exit"], &["gnome-session-quit", "--logout", "--no-prompt"], &["kdeinit5", "--logout"], &["logout"]]),
+            suspend: commands(&[&["systemctl", "suspend"], &["loginctl", "suspend"], &["zzz"]]),
+            hibernate: commands(&[&["systemctl", "hibernate"], &["loginctl", "hibernate"], &["ZZZ"]]),
+            hybrid_sleep: commands(&[&["systemctl", "hybrid-sleep"], &["loginctl", "hybrid-sleep"]]),
+            suspend_then_hibernate: commands(&[&["systemctl", "suspend-then-hibernate"], &["loginctl", "suspend-then-hibernate"]]),
+            lock: commands(&[&["loginctl", "lock-session"], &["swaylock"], &["hyprlock"], &["gtklock"]]),
+            countdown: PowerCountdown::default(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// Launch timestamps kept per application for the frecency average.
+    pub launches_per_app: usize,
+    /// Applications remembered at most; the least frecent are dropped first.
+    pub max_apps: usize,
+    /// A launch counts half as much after this many days.
+    pub half_life_days: f64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self { launches_per_app: 10, max_apps: 200, half_life_days: 14.0 }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppOverride {
+    /// Extra environment variables for the spawned process.
+    pub env: HashMap<String, String>,
+    /// Working directory, takes precedence over the entry's `Path=`. A leading `~` is expanded.
+    pub working_dir: Option<PathBuf>,
+    /// Command prepended to the entry's Exec, e.g. `"gamemoderun"` or `"env GDK_BACKEND=x11"`.
+    pub prefix: Option<String>,
+}
+
+impl Config {
+    /// Checks what the types alone do not. Errors name the offending key.
+    fn validate(&self) -> Result<(), String> {
+        if self.max_results == 0 {
+            return Err("max_results: must be at least 1".to_string());
+        }
+        if !(self.window.width > 0.0 && self.window.width.is_finite()) {
+            return Err(format!("window.width: must be positive, got {}", self.window.width));
+        }
+        if !(self.window.height > 0.0 && self.window.height.is_finite()) {
+            return Err(format!("window.height: must be positive, got {}", self.window.height));
+        }
+        if let Some(accent) = self.theme.accent.as_deref().filter(|accent| parse_hex_color(accent).is_none()) {
+            return Err(format!("theme.accent: expected a #rrggbb color, got {:?}", accent));
+        }
+        let invalid = chrono::format::StrftimeItems::new(&self.clock.format)
+            .any(|item| item == chrono::format::Item::Error);
+        if invalid {
+            return Err(format!("clock.format: invalid strftime format {:?}", self.clock.format));
+        }
+        for (key, commands) in self.power.command_lists() {
+            if let Some(index) = commands.iter().position(|argv| argv.first().is_none_or(|program| program.is_empty())) {
+                return Err(format!("power.{}[{}]: command must start with a program", key, index));
+            }
+        }
+        if self.history.launches_per_app == 0 {
+            return Err("history.launches_per_app: must be at least 1".to_string());
+        }
+        if !(self.history.half_life_days > 0.0 && self.history.half_life_days.is_finite()) {
+            return Err(format!("history.half_life_days: must be positive, got {}", self.history.half_life_days));
+        }
+        for (id, app_override) in &self.apps {
+            if app_override.prefix.as_deref().is_some_and(|prefix| prefix.trim().is_empty()) {
+                return Err(format!("apps.\"{}\".prefix: must not be empty", id));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Set by `--config` to use another file than the one in the XDG config directories. Has to be
+/// set before the configuration is first used.
+pub static CONFIG_PATH: OnceCell<PathBuf> = OnceCell::new();
+
+/// Where the configuration is read from: the `--config` file, or the first `config.toml` found
+/// in the XDG config directories.
+fn config_file() -> Option<PathBuf> {
+    CONFIG_PATH.get().cloned()
+        .or_else(|| BaseDirectories::with_prefix("rustrocket").ok().and_then(|dirs| dirs.find_config_file("config.toml")))
+}
+
+/// Every file `config_file` may pick, so a watcher notices one being created.
+pub fn config_file_candidates() -> Vec<PathBuf> {
+    if let Some(path) = CONFIG_PATH.get() {
+        return vec![path.clone()];
+    }
+    let dirs = match BaseDirectories::with_prefix("rustrocket") {
+        Ok(dirs) => dirs,
+        Err(_) => return Vec::new(),
+    };
+    std::iter::once(dirs.get_config_home())
+        .chain(dirs.get_config_dirs())
+        .map(|dir| dir.join("config.toml"))
+        .collect()
+}
+
+/// The configuration in `config_file`, or the defaults if there is none.
+fn read_config() -> Result<Config, String> {
+    let path = match config_file() {
+        Some(path) => path,
+        None => return Ok(Config::default()),
+    };
+    // toml errors already point at the line and key; ours name the key
+    fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| toml::from_str::<Config>(&content).map_err(|e| e.to_string()))
+        .and_then(|config| config.validate().map(|()| config))
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// The active configuration. Replaced as a whole on reload, so a snapshot is always consistent.
+static CONFIG: Lazy<RwLock<Arc<Config>>> = Lazy::new(|| {
+    let config = read_config().unwrap_or_else(|err| {
+        eprintln!("Ignoring {}", err);
+        *CONFIG_ERROR.lock().unwrap() = Some(err);
+        Config::default()
+    });
+    RwLock::new(Arc::new(config))
+});
+
+/// Why the configuration file was last rejected, if it was.
+static CONFIG_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+/// A snapshot of the active configuration. Hold on to it for the duration of an operation
+/// rather than calling this repeatedly, so a reload cannot change settings halfway through.
+pub fn config() -> Arc<Config> {
+    CONFIG.read().unwrap().clone()
+}
+
+pub fn config_error() -> Option<String> {
+    Lazy::force(&CONFIG);
+    CONFIG_ERROR.lock().unwrap().clone()
+}
+
+/// Re-reads the configuration file. A file that fails to parse or validate leaves the active
+/// configuration in place and is reported through `config_error`.
+pub fn reload_config() {
+    match read_config() {
+        Ok(config) => {
+            *CONFIG.write().unwrap() = Arc::new(config);
+            *CONFIG_ERROR.lock().unwrap() = None;
+        }
+        Err(err) => {
+            eprintln!("Keeping the previous configuration, {}", err);
+            *CONFIG_ERROR.lock().unwrap() = Some(err);
+        }
+    }
+}
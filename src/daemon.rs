@@ -0,0 +1,170 @@
+use std::{
+    error::Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+};
+use crate::app_launcher::{AppLauncher, LauncherOptions};
+use crate::config::reload_config;
+use crate::gui_trait::{Backend, WindowSlot};
+use crate::ipc::{self, Request};
+use crate::modes::Mode;
+use crate::power::PowerAction;
+use crate::watch::{watch_applications, watch_config};
+
+/// Answers control requests. Requests for an open window are handled right here through its
+/// handle; a request to show a hidden window is passed to the main thread, which owns the GUI.
+fn handler(window: WindowSlot, visible: Arc<AtomicBool>, show: mpsc::Sender<()>) -> impl Fn(Request) -> Result<(), String> {
+    move |request| {
+        let window = window.lock().map_err(|_| "window state is poisoned".to_string())?;
+        match (request, window.as_ref()) {
+            (Request::Ping, _) => Ok(()),
+            (Request::Show, Some(handle)) => {
+                handle.focus();
+                Ok(())
+            }
+            (Request::Hide | Request::Toggle, Some(handle)) => {
+                handle.close();
+                Ok(())
+            }
+            // The window is still being created
+            (_, None) if visible.load(Ordering::SeqCst) => Ok(()),
+            (Request::Show | Request::Toggle, None) => show.send(()).map_err(|_| "launcher is shutting down".to_string()),
+            (Request::Hide, None) => Ok(()),
+        }
+    }
+}
+
+/// Reloads the config whenever the file changes and redraws an open window so it picks it up.
+/// The power actions depend on the config, so they are worked out again here rather than by the
+/// window.
+fn follow_config(window: WindowSlot) {
+    let reloaded = watch_config(move || {
+        reload_config();
+        PowerAction::available();
+        if let Some(handle) = window.lock().unwrap().as_ref() {
+            handle.refresh();
+        }
+    });
+    if let Err(err) = reloaded {
+        eprintln!("Failed to watch the config file: {}", err);
+    }
+}
+
+/// `--daemon`: keeps the applications loaded and opens a launcher window whenever asked to over
+/// the control socket. Only the applications stay warm: every show builds a new launcher and
+/// window, and hiding destroys the window, as Wayland clients cannot hide a window of their
+/// own. The applications are kept current while we wait, so newly installed ones show up on
+/// the next show.
+pub fn run_daemon(options: LauncherOptions, backend: Backend) -> Result<(), Box<dyn Error>> {
+    let window = WindowSlot::default();
+    let visible = Arc::new(AtomicBool::new(false));
+    let (show_sender, show_requests) = mpsc::channel();
+    let _server = ipc::serve(handler(window.clone(), visible.clone(), show_sender))?
+        .ok_or("Another RustRocket instance is already running")?;
+
+    follow_config(window.clone());
+    // Asking logind for the power actions takes a while; do it before the first show
+    thread::spawn(PowerAction::available);
+    let applications = Arc::new(Mutex::new(options.mode.load_items()?));
+    if options.mode == Mode::Drun {
+        let latest = applications.clone();
+        if let Err(err) = watch_applications(move |reloaded| *latest.lock().unwrap() = reloaded) {
+            eprintln!("Failed to watch application directories: {}", err);
+        }
+    }
+
+    for () in show_requests.iter() {
+        visible.store(true, Ordering::SeqCst);
+        let current = applications.lock().unwrap().clone();
+        let result = backend.run(Box::new(AppLauncher::new(current, options.clone())), window.clone());
+        visible.store(false, Ordering::SeqCst);
+        result?;
+    }
+    Ok(())
+}
+
+/// A single launcher window that exits once closed, returning whether anything was picked.
+/// While an application launcher is open it owns the control socket, so a second invocation
+/// focuses it instead of opening another one.
+fn run_standalone(options: LauncherOptions, backend: Backend) -> Result<bool, Box<dyn Error>> {
+    let window = WindowSlot::default();
+    let (show_sender, _show_requests) = mpsc::channel();
+    let _server = match options.mode {
+        Mode::Drun => ipc::serve(handler(window.clone(), Arc::new(AtomicBool::new(true)), show_sender))
+            .unwrap_or_else(|err| {
+                eprintln!("Control socket unavailable: {}", err);
+                None
+            }),
+        _ => None,
+    };
+    follow_config(window.clone());
+    let launcher = AppLauncher::new(options.mode.load_items()?, options);
+    let launched = launcher.launched();
+    backend.run(Box::new(launcher), window)?;
+    Ok(launched.load(Ordering::SeqCst))
+}
+
+/// Whether a running instance can show the window `options` and `backend` ask for. Its window
+/// is a plain application launcher with the daemon's own settings, so anything else, e.g. `-n`
+/// or `--no-confirm`, gets a window of its own rather than being dropped.
+fn can_forward(options: &LauncherOptions, backend: Backend) -> bool {
+    options.mode == Mode::Drun
+        && options.query.is_empty()
+        && options.max_results.is_none()
+        && !options.no_confirm
+        && backend == Backend::default()
+}
+
+/// Forwards `request` to a running instance, or opens a window ourselves if there is none.
+/// Used by a plain invocation as well as `--show` and `--toggle`. Only a plain application
+/// launcher is forwarded, see `can_forward`.
+/// Returns whether anything was picked, which for a forwarded request we take for granted.
+pub fn show_or_run(request: Request, options: LauncherOptions, backend: Backend) -> Result<bool, Box<dyn Error>> {
+    if !can_forward(&options, backend) {
+        return run_standalone(options, backend);
+    }
+    match ipc::send(request) {
+        Ok(Some(response)) => response.map(|()| true).map_err(Into::into),
+        Ok(None) => run_standalone(options, backend),
+        Err(err) => {
+            eprintln!("Failed to reach a running instance: {}", err);
+            run_standalone(options, backend)
+        }
+    }
+}
+
+/// `--hide`: there is nothing to do when no instance is running.
+pub fn hide() -> Result<(), Box<dyn Error>> {
+    match ipc::send(Request::Hide)? {
+        Some(response) => Ok(response?),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain() -> LauncherOptions {
+        LauncherOptions { mode: Mode::Drun, query: String::new(), max_results: None, no_confirm: false }
+    }
+
+    #[test]
+    fn only_plain_launchers_are_forwarded() {
+        assert!(can_forward(&plain(), Backend::Eframe));
+        let changes: [fn(&mut LauncherOptions); 4] = [
+            |options| options.mode = Mode::Run,
+            |options| options.query = "fire".to_string(),
+            |options| options.max_results = Some(3),
+            |options| options.no_confirm = true,
+        ];
+        for change in changes {
+            let mut options = plain();
+            change(&mut options);
+            assert!(!can_forward(&options, Backend::Eframe));
+        }
+    }
+}
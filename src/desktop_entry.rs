@@ -0,0 +1,411 @@
+use std::{
+    env,
+    error::Error,
+    fmt, fs, io,
+    path::Path,
+};
+
+pub const DESKTOP_ENTRY_GROUP: &str = "Desktop Entry";
+
+#[derive(Debug)]
+pub enum ParseError {
+    Io(io::Error),
+    InvalidLine(usize),
+    InvalidGroupHeader(usize),
+    EntryOutsideGroup(usize),
+    InvalidKey(usize, String),
+    DuplicateGroup(usize, String),
+    MissingDesktopEntryGroup,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(err) => write!(f, "{}", err),
+            ParseError::InvalidLine(line) => write!(f, "line {}: expected `Key=Value`", line),
+            ParseError::InvalidGroupHeader(line) => write!(f, "line {}: malformed group header", line),
+            ParseError::EntryOutsideGroup(line) => write!(f, "line {}: entry before the first group", line),
+            ParseError::InvalidKey(line, key) => write!(f, "line {}: invalid key `{}`", line, key),
+            ParseError::DuplicateGroup(line, group) => write!(f, "line {}: duplicate group [{}]", line, group),
+            ParseError::MissingDesktopEntryGroup => write!(f, "first group is not [{}]", DESKTOP_ENTRY_GROUP),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<io::Error> for ParseError {
+    fn from(err: io::Error) -> Self {
+        ParseError::Io(err)
+    }
+}
+
+/// A POSIX message locale, `lang_COUNTRY.ENCODING@MODIFIER` with the encoding dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Locale {
+    lang: String,
+    country: Option<String>,
+    modifier: Option<String>,
+}
+
+impl Locale {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (rest, modifier) = match value.split_once('@') {
+            Some((rest, modifier)) => (rest, Some(modifier.to_string())),
+            None => (value, None),
+        };
+        let rest = rest.split('.').next().unwrap_or(rest);
+        let (lang, country) = match rest.split_once('_') {
+            Some((lang, country)) => (lang, Some(country.to_string())),
+            None => (rest, None),
+        };
+        if lang.is_empty() || lang == "C" || lang == "POSIX" {
+            return None;
+        }
+        Some(Self { lang: lang.to_string(), country, modifier })
+    }
+
+    /// The message locale from `LC_ALL`, `LC_MESSAGES` or `LANG`, whichever is set first.
+    pub fn from_env() -> Option<Self> {
+        ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::parse(&value))
+    }
+
+    /// Locale suffixes to try, most specific first: `lang_COUNTRY@MODIFIER`, `lang_COUNTRY`,
+    /// `lang@MODIFIER`, `lang`.
+    pub fn candidates(&self) -> Vec<String> {
+        let mut candidates = Vec::with_capacity(4);
+        if let (Some(country), Some(modifier)) = (&self.country, &self.modifier) {
+            candidates.push(format!("{}_{}@{}", self.lang, country, modifier));
+        }
+        if let Some(country) = &self.country {
+            candidates.push(format!("{}_{}", self.lang, country));
+        }
+        if let Some(modifier) = &self.modifier {
+            candidates.push(format!("{}@{}", self.lang, modifier));
+        }
+        candidates.push(self.lang.clone());
+        candidates
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    key: String,
+    locale: Option<String>,
+    value: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Group {
+    name: String,
+    entries: Vec<Entry>,
+}
+
+impl Group {
+    /// Raw, still escaped value of the unlocalized `key`.
+    pub fn raw(&self, key: &str) -> Option<&str> {
+        self.raw_localized(key, None)
+    }
+
+    /// Raw value of `key[locale]`, or of the plain `key` when `locale` is `None`.
+    pub fn raw_localized(&self, key: &str, locale: Option<&str>) -> Option<&str> {
+        self.entries.iter()
+            .find(|entry| entry.key == key && entry.locale.as_deref() == locale)
+            .map(|entry| entry.value.as_str())
+    }
+
+    /// Raw value of the best translation of `key` for `locale`, falling back to the
+    /// unlocalized value.
+    pub fn raw_for_locale(&self, key: &str, locale: Option<&Locale>) -> Option<&str> {
+        locale.into_iter()
+            .flat_map(Locale::candidates)
+            .find_map(|candidate| self.raw_localized(key, Some(&candidate)))
+            .or_else(|| self.raw(key))
+    }
+
+    pub fn string(&self, key: &str) -> Option<String> {
+        self.raw(key).map(unescape)
+    }
+
+    pub fn locale_string(&self, key: &str, locale: Option<&Locale>) -> Option<String> {
+        self.raw_for_locale(key, locale).map(unescape)
+    }
+
+    pub fn strings(&self, key: &str) -> Option<Vec<String>> {
+        self.raw(key).map(split_list)
+    }
+
+    pub fn locale_strings(&self, key: &str, locale: Option<&Locale>) -> Option<Vec<String>> {
+        self.raw_for_locale(key, locale).map(split_list)
+    }
+
+    pub fn boolean(&self, key: &str) -> Option<bool> {
+        match self.raw(key)? {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DesktopEntry {
+    groups: Vec<Group>,
+}
+
+impl DesktopEntry {
+    pub fn from_path(path: &Path) -> Result<Self, ParseError> {
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, ParseError> {
+        let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+        let mut groups: Vec<Group> = Vec::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                let name = line.strip_prefix('[')
+                    .and_then(|rest| rest.strip_suffix(']'))
+                    .filter(|name| !name.is_empty() && !name.contains(|c: char| c == '[' || c == ']' || c.is_control()))
+                    .ok_or(ParseError::InvalidGroupHeader(line_number))?;
+                if groups.iter().any(|group| group.name == name) {
+                    return Err(ParseError::DuplicateGroup(line_number, name.to_string()));
+                }
+                groups.push(Group { name: name.to_string(), entries: Vec::new() });
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or(ParseError::InvalidLine(line_number))?;
+            let (key, locale) = parse_key(key.trim_end())
+                .ok_or_else(|| ParseError::InvalidKey(line_number, key.trim_end().to_string()))?;
+            let group = groups.last_mut().ok_or(ParseError::EntryOutsideGroup(line_number))?;
+
+            // Duplicate keys are invalid, but common enough in the wild that we keep the first one
+            // instead of rejecting the whole file.
+            if group.raw_localized(key, locale).is_none() {
+                group.entries.push(Entry {
+                    key: key.to_string(),
+                    locale: locale.map(str::to_string),
+                    value: value.trim_start().to_string(),
+                });
+            }
+        }
+
+        match groups.first() {
+            Some(group) if group.name == DESKTOP_ENTRY_GROUP => Ok(Self { groups }),
+            _ => Err(ParseError::MissingDesktopEntryGroup),
+        }
+    }
+
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    /// The mandatory `[Desktop Entry]` group.
+    pub fn main_group(&self) -> &Group {
+        &self.groups[0]
+    }
+}
+
+/// Splits `Name[de_DE@euro]` into `("Name", Some("de_DE@euro"))`.
+fn parse_key(key: &str) -> Option<(&str, Option<&str>)> {
+    let (name, locale) = match key.find('[') {
+        Some(start) => {
+            let locale = key[start + 1..].strip_suffix(']')?;
+            if locale.is_empty() || locale.contains(|c: char| c == '[' || c == ']' || c.is_whitespace()) {
+                return None;
+            }
+            (&key[..start], Some(locale))
+        }
+        None => (key, None),
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+    Some((name, locale))
+}
+
+/// Resolves the `\s`, `\n`, `\t`, `\r`, `\\` and `\;` escapes of a string value.
+/// Unknown escapes are kept verbatim.
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => result.push(' '),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('\\') => result.push('\\'),
+            Some(';') => result.push(';'),
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+
+/// Splits a `;` separated list, honoring `\;` and the regular string escapes.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => current.push(';'),
+                Some(other) => {
+                    current.push('\\');
+                    current.push(other);
+                }
+                None => current.push('\\'),
+            },
+            ';' => items.push(unescape(&std::mem::take(&mut current))),
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        items.push(unescape(&current));
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> Result<DesktopEntry, ParseError> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/desktop").join(name);
+        DesktopEntry::from_path(&path)
+    }
+
+    fn locale(value: &str) -> Locale {
+        Locale::parse(value).unwrap()
+    }
+
+    #[test]
+    fn reads_desktop_action_groups() {
+        let entry = fixture("browser.desktop").unwrap();
+        assert_eq!(entry.main_group().strings("Actions").unwrap(), ["new-window", "new-private-window"]);
+
+        let new_window = entry.group("Desktop Action new-window").unwrap();
+        assert_eq!(new_window.string("Name").as_deref(), Some("New Window"));
+        assert_eq!(new_window.locale_string("Name", Some(&locale("de_AT"))).as_deref(), Some("Neues Fenster"));
+        assert_eq!(new_window.string("Exec").as_deref(), Some("browser --new-window"));
+
+        let private = entry.group("Desktop Action new-private-window").unwrap();
+        assert_eq!(private.string("Exec").as_deref(), Some("browser --private-window"));
+        assert!(entry.group("Desktop Action missing").is_none());
+    }
+
+    #[test]
+    fn translations_before_the_untranslated_key() {
+        let main = fixture("browser.desktop").unwrap().main_group().clone();
+        assert_eq!(main.string("Name").as_deref(), Some("Web Browser"));
+        assert_eq!(main.locale_string("Name", None).as_deref(), Some("Web Browser"));
+        assert_eq!(main.locale_string("Name", Some(&locale("fr_FR.UTF-8"))).as_deref(), Some("Web Browser"));
+        assert_eq!(main.locale_string("Name", Some(&locale("de_DE.UTF-8"))).as_deref(), Some("Netzbrowser"));
+        assert_eq!(main.locale_string("Name", Some(&locale("sr_RS@latin"))).as_deref(), Some("Pregledač"));
+        assert_eq!(main.locale_string("GenericName", Some(&locale("de_DE"))).as_deref(), Some("Webbrowser"));
+        assert_eq!(main.locale_string("GenericName", Some(&locale("de_AT"))).as_deref(), Some("Browser"));
+    }
+
+    #[test]
+    fn resolves_escapes() {
+        let entry = fixture("escapes.desktop").unwrap();
+        let main = entry.main_group();
+        assert_eq!(main.string("Comment").as_deref(), Some("Two spaces:  and\na\tnew\rline"));
+        assert_eq!(main.string("Exec").as_deref(), Some("sh -c \"echo \\\\$HOME\""));
+        assert_eq!(main.string("Unknown").as_deref(), Some("kept\\qverbatim\\"));
+    }
+
+    #[test]
+    fn splits_lists() {
+        let entry = fixture("escapes.desktop").unwrap();
+        let main = entry.main_group();
+        assert_eq!(main.strings("Keywords").unwrap(), ["semi;colon", "back\\slash", "trailing"]);
+        assert_eq!(main.strings("Categories").unwrap(), ["Utility", "Development"]);
+        assert_eq!(split_list(r"a\;b;;c;"), ["a;b", "", "c"]);
+        assert!(split_list("").is_empty());
+    }
+
+    #[test]
+    fn skips_byte_order_mark() {
+        let entry = fixture("bom.desktop").unwrap();
+        assert_eq!(entry.main_group().string("Name").as_deref(), Some("With BOM"));
+    }
+
+    #[test]
+    fn keeps_first_of_duplicate_keys() {
+        let entry = fixture("duplicate_keys.desktop").unwrap();
+        let main = entry.main_group();
+        assert_eq!(main.string("Name").as_deref(), Some("First"));
+        assert_eq!(main.locale_string("Name", Some(&locale("fr"))).as_deref(), Some("Premier"));
+    }
+
+    #[test]
+    fn rejects_duplicate_groups() {
+        let err = fixture("duplicate_group.desktop").unwrap_err();
+        assert!(matches!(err, ParseError::DuplicateGroup(7, ref group) if group == "Desktop Action new"), "{:?}", err);
+    }
+
+    #[test]
+    fn requires_desktop_entry_first() {
+        for name in ["action_first.desktop", "no_desktop_entry.desktop"] {
+            let err = fixture(name).unwrap_err();
+            assert!(matches!(err, ParseError::MissingDesktopEntryGroup), "{}: {:?}", name, err);
+        }
+    }
+
+    #[test]
+    fn rejects_entries_outside_groups() {
+        let err = fixture("entry_outside_group.desktop").unwrap_err();
+        assert!(matches!(err, ParseError::EntryOutsideGroup(2)), "{:?}", err);
+    }
+
+    #[test]
+    fn rejects_malformed_group_headers() {
+        let err = fixture("unclosed_header.desktop").unwrap_err();
+        assert!(matches!(err, ParseError::InvalidGroupHeader(3)), "{:?}", err);
+        let err = fixture("nested_header.desktop").unwrap_err();
+        assert!(matches!(err, ParseError::InvalidGroupHeader(2)), "{:?}", err);
+    }
+
+    #[test]
+    fn rejects_malformed_keys() {
+        let err = fixture("invalid_key.desktop").unwrap_err();
+        assert!(matches!(err, ParseError::InvalidKey(3, ref key) if key == "X_Underscore"), "{:?}", err);
+        let err = fixture("empty_locale.desktop").unwrap_err();
+        assert!(matches!(err, ParseError::InvalidKey(2, ref key) if key == "Name[]"), "{:?}", err);
+    }
+
+    #[test]
+    fn rejects_lines_without_equals_sign() {
+        let err = fixture("invalid_line.desktop").unwrap_err();
+        assert!(matches!(err, ParseError::InvalidLine(4)), "{:?}", err);
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        assert!(matches!(fixture("does_not_exist.desktop"), Err(ParseError::Io(_))));
+    }
+}
@@ -1,40 +1,208 @@
+use std::sync::Arc;
 use eframe::egui;
-use crate::gui_trait::{GuiFramework, AppInterface};
+use crate::config::{config, Config};
+use crate::gui_trait::{GuiFramework, AppInterface, LauncherEvent, Movement, WindowHandle, WindowSlot};
 
 pub struct EframeGui;
 
+struct EframeWindow(egui::Context);
+
+impl WindowHandle for EframeWindow {
+    fn focus(&self) {
+        self.0.send_viewport_cmd(egui::ViewportCommand::Focus);
+        self.0.request_repaint();
+    }
+
+    fn close(&self) {
+        self.0.send_viewport_cmd(egui::ViewportCommand::Close);
+        self.0.request_repaint();
+    }
+
+    fn refresh(&self) {
+        self.0.request_repaint();
+    }
+}
+
 impl GuiFramework for EframeGui {
-    fn run(app: Box<dyn AppInterface>) -> Result<(), Box<dyn std::error::Error>> {
+    fn run(app: Box<dyn AppInterface>, window: WindowSlot) -> Result<(), Box<dyn std::error::Error>> {
+        let config = config();
         let native_options = eframe::NativeOptions {
             viewport: egui::ViewportBuilder::default()
-                .with_inner_size([300.0, 400.0]),
+                .with_inner_size([config.window.width, config.window.height]),
             ..Default::default()
         };
-        eframe::run_native(
+        let slot = window.clone();
+        let result = eframe::run_native(
             "Application Launcher",
             native_options,
-            Box::new(|_cc| Box::new(EframeWrapper {
-                app,
-                focused: false,
-            })),
-        )?;
+            Box::new(move |cc| {
+                *slot.lock().unwrap() = Some(Box::new(EframeWindow(cc.egui_ctx.clone())));
+                apply_theme(&cc.egui_ctx, &config);
+                Box::new(EframeWrapper {
+                    app,
+                    focused: false,
+                    config,
+                })
+            }),
+        );
+        *window.lock().unwrap() = None;
+        result?;
         Ok(())
     }
 }
 
+fn accent_color(config: &Config) -> Option<egui::Color32> {
+    config.theme.accent_rgb().map(|[r, g, b]| egui::Color32::from_rgb(r, g, b))
+}
+
+fn apply_theme(ctx: &egui::Context, config: &Config) {
+    let mut visuals = match config.theme.dark {
+        Some(true) => egui::Visuals::dark(),
+        Some(false) => egui::Visuals::light(),
+        None => ctx.style().visuals.clone(),
+    };
+    if let Some(accent) = accent_color(config) {
+        visuals.selection.bg_fill = accent;
+        visuals.hyperlink_color = accent;
+    }
+    ctx.set_visuals(visuals);
+}
+
+/// Keys that move the selection. Shift+Tab has to come before Tab, which would match it too.
+const SELECTION_KEYS: [(egui::Modifiers, egui::Key, Movement); 10] = [
+    (egui::Modifiers::SHIFT, egui::Key::Tab, Movement::Up),
+    (egui::Modifiers::NONE, egui::Key::Tab, Movement::Down),
+    (egui::Modifiers::NONE, egui::Key::ArrowUp, Movement::Up),
+    (egui::Modifiers::NONE, egui::Key::ArrowDown, Movement::Down),
+    (egui::Modifiers::CTRL, egui::Key::P, Movement::Up),
+    (egui::Modifiers::CTRL, egui::Key::N, Movement::Down),
+    (egui::Modifiers::NONE, egui::Key::PageUp, Movement::PageUp),
+    (egui::Modifiers::NONE, egui::Key::PageDown, Movement::PageDown),
+    (egui::Modifiers::NONE, egui::Key::Home, Movement::First),
+    (egui::Modifiers::NONE, egui::Key::End, Movement::Last),
+];
+
+fn search_bar_id() -> egui::Id {
+    egui::Id::new("search_bar")
+}
+
+/// Whether the search bar's text cursor sits at the end of `query` with nothing selected.
+fn cursor_at_end(ctx: &egui::Context, query: &str) -> bool {
+    let end = query.chars().count();
+    egui::TextEdit::load_state(ctx, search_bar_id())
+        .and_then(|state| state.cursor.char_range())
+        .is_none_or(|range| range.primary.index == end && range.secondary.index == end)
+}
+
+/// `text` with the chars at `highlights` drawn in the accent or strong text color.
+fn highlighted_label(ui: &egui::Ui, text: &str, highlights: &[usize], accent: Option<egui::Color32>) -> egui::text::LayoutJob {
+    let style = ui.style();
+    let font_id = egui::TextStyle::Button.resolve(style);
+    let normal = egui::TextFormat::simple(font_id.clone(), style.visuals.text_color());
+    let color = accent.unwrap_or_else(|| style.visuals.strong_text_color());
+    let highlighted = egui::TextFormat {
+        color,
+        underline: egui::Stroke::new(1.0, color),
+        ..egui::TextFormat::simple(font_id, style.visuals.text_color())
+    };
+    let mut job = egui::text::LayoutJob::default();
+    for (index, c) in text.chars().enumerate() {
+        let format = if highlights.contains(&index) { &highlighted } else { &normal };
+        job.append(c.encode_utf8(&mut [0; 4]), 0.0, format.clone());
+    }
+    job
+}
+
 struct EframeWrapper {
     app: Box<dyn AppInterface>,
     focused: bool,
+    /// The config the window currently reflects.
+    config: Arc<Config>,
+}
+
+impl EframeWrapper {
+    /// Applies a config that was reloaded since the last frame.
+    fn follow_config(&mut self, ctx: &egui::Context) {
+        let latest = config();
+        if Arc::ptr_eq(&latest, &self.config) {
+            return;
+        }
+        if (latest.window.width, latest.window.height) != (self.config.window.width, self.config.window.height) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(latest.window.width, latest.window.height)));
+        }
+        apply_theme(ctx, &latest);
+        self.config = latest;
+        self.app.handle_event(LauncherEvent::ConfigChanged);
+    }
 }
 
 impl eframe::App for EframeWrapper {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        self.app.update();
+        self.follow_config(ctx);
+        let accent = accent_color(&self.config);
+
+        // Consumed before the search bar is drawn, which would otherwise move its text cursor
+        let movements: Vec<Movement> = ctx.input_mut(|i| {
+            SELECTION_KEYS.iter()
+                .filter(|(modifiers, key, _)| i.consume_key(*modifiers, *key))
+                .map(|(_, _, movement)| *movement)
+                .collect()
+        });
+        for movement in movements {
+            self.app.handle_event(LauncherEvent::MoveSelection(movement));
+        }
+
+        self.app.handle_event(LauncherEvent::Tick);
+
+        // Events are collected while drawing and handled afterwards, so a frame draws one state
+        let view = self.app.view();
+        let mut events = Vec::new();
+
+        // Right expands and Left collapses the actions of the selected result. Only with the text
+        // cursor at the end of the query, and then consumed before the search bar sees them;
+        // anywhere else they keep moving the cursor.
+        if let Some(row) = view.selected {
+            if cursor_at_end(ctx, &view.query) {
+                let expanded = view.results[row].expanded;
+                let key = if expanded { egui::Key::ArrowLeft } else { egui::Key::ArrowRight };
+                if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, key)) {
+                    events.push(LauncherEvent::ToggleActions(row));
+                }
+            }
+        }
+
+        if let Some(prompt) = view.power_prompt {
+            egui::TopBottomPanel::top("power_prompt").show(ctx, |ui| {
+                ui.strong(format!("{} in {} s", prompt.action.label(), prompt.seconds_left));
+                ui.horizontal(|ui| {
+                    if ui.button(format!("{} now (Enter)", prompt.action.label())).clicked() {
+                        events.push(LauncherEvent::ConfirmPower);
+                    }
+                    if ui.button("Cancel (Esc)").clicked() {
+                        events.push(LauncherEvent::CancelPower);
+                    }
+                });
+            });
+            // Keep the countdown running without input
+            ctx.request_repaint_after(std::time::Duration::from_millis(250));
+        }
+
+        if view.busy {
+            ctx.request_repaint_after(std::time::Duration::from_millis(50));
+        }
+
+        if let Some(error) = &view.config_error {
+            egui::TopBottomPanel::top("config_error").show(ctx, |ui| {
+                ui.colored_label(ui.visuals().error_fg_color, "Config error, previous settings kept:");
+                ui.label(egui::RichText::new(error).small());
+            });
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.with_layout(egui::Layout::top_down(egui::Align::LEFT), |ui| {
                 // Search bar
-                let mut query = self.app.get_query();
-                let search_response = ui.add(egui::TextEdit::singleline(&mut query).hint_text("Search..."));
+                let mut query = view.query.clone();
+                let search_response = ui.add(egui::TextEdit::singleline(&mut query).id(search_bar_id()).hint_text("Search...").lock_focus(true));
 
                 // Request focus on the search bar if not yet focused
                 if !self.focused {
@@ -43,49 +211,64 @@ impl eframe::App for EframeWrapper {
                 }
 
                 if search_response.changed() {
-                    self.app.handle_input(&query);
+                    events.push(LauncherEvent::QueryChanged(query));
                 }
                 
                 ui.add_space(10.0);
 
                 // Search results
-                for result in self.app.get_search_results() {
-                    if ui.button(&result).clicked() {
-                        self.app.launch_app(&result);
-                    }
+                for (row, result) in view.results.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if result.has_actions {
+                            let arrow = if result.expanded { "▾" } else { "▸" };
+                            if ui.small_button(arrow).clicked() {
+                                events.push(LauncherEvent::ToggleActions(row));
+                            }
+                        }
+                        let label = highlighted_label(ui, &result.label, &result.highlights, accent);
+                        let mut response = ui.add(egui::Button::new(label).selected(view.selected == Some(row)));
+                        if let Some(description) = &result.description {
+                            response = response.on_hover_text(description);
+                        }
+                        if response.clicked() {
+                            events.push(LauncherEvent::Activate(row));
+                        }
+                        if let Some(field) = &result.matched_field {
+                            ui.weak(format!("({})", field));
+                        }
+                    });
                 }
             });
 
             // Push everything else to the bottom
             ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
-                // Power, Restart, Logout buttons
-                ui.horizontal(|ui| {
-                    if ui.button("Power").clicked() {
-                        self.app.handle_input("P");
-                    }
-                    if ui.button("Restart").clicked() {
-                        self.app.handle_input("R");
-                    }
-                    if ui.button("Logout").clicked() {
-                        self.app.handle_input("L");
+                // Wrapped, since there are up to eight of them
+                ui.horizontal_wrapped(|ui| {
+                    for &action in &view.power_actions {
+                        if ui.button(action.label()).clicked() {
+                            events.push(LauncherEvent::PowerAction(action));
+                        }
                     }
                 });
 
                 ui.add_space(5.0);
 
                 // Time display
-                ui.label(self.app.get_time());
+                ui.label(&view.time);
             });
         });
 
         if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
-            self.app.handle_input("ESC");
+            events.push(LauncherEvent::Cancel);
         }
         if ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
-            self.app.handle_input("ENTER");
+            events.push(LauncherEvent::Submit);
         }
 
-        if self.app.should_quit() {
+        for event in events {
+            self.app.handle_event(event);
+        }
+        if self.app.view().should_quit {
             ctx.request_repaint(); // Ensure the UI is updated immediately
             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
         }
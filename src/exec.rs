@@ -0,0 +1,187 @@
+use std::{
+    env,
+    error::Error,
+    fmt, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum ExecError {
+    Empty,
+    UnterminatedQuote,
+    InvalidEscape(char),
+    InvalidFieldCode(char),
+    DanglingPercent,
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecError::Empty => write!(f, "empty Exec command"),
+            ExecError::UnterminatedQuote => write!(f, "unterminated quote in Exec"),
+            ExecError::InvalidEscape(c) => write!(f, "invalid escape `\\{}` in quoted Exec argument", c),
+            ExecError::InvalidFieldCode(c) => write!(f, "invalid field code `%{}` in Exec", c),
+            ExecError::DanglingPercent => write!(f, "Exec ends with a lone `%`"),
+        }
+    }
+}
+
+impl Error for ExecError {}
+
+/// What the `%i`, `%c` and `%k` field codes expand to.
+pub struct ExecContext<'a> {
+    pub icon: Option<&'a str>,
+    pub name: &'a str,
+    pub desktop_file: &'a Path,
+}
+
+enum Segment {
+    Literal(String),
+    FieldCode(char),
+}
+
+/// Splits an (already string-unescaped) Exec value into arguments following the quoting rules
+/// of the Desktop Entry spec. Field codes are only recognized outside of quotes, but `%%` is a
+/// literal `%` everywhere.
+fn tokenize(exec: &str) -> Result<Vec<Vec<Segment>>, ExecError> {
+    let mut args = Vec::new();
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut in_arg = false;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                if in_arg {
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    args.push(std::mem::take(&mut segments));
+                    in_arg = false;
+                }
+            }
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next().ok_or(ExecError::UnterminatedQuote)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or(ExecError::UnterminatedQuote)? {
+                            escaped @ ('"' | '`' | '$' | '\\') => literal.push(escaped),
+                            other => return Err(ExecError::InvalidEscape(other)),
+                        },
+                        '%' => {
+                            if chars.as_str().starts_with('%') {
+                                chars.next();
+                            }
+                            literal.push('%');
+                        }
+                        other => literal.push(other),
+                    }
+                }
+            }
+            '%' => {
+                in_arg = true;
+                match chars.next() {
+                    Some('%') => literal.push('%'),
+                    Some(code) => {
+                        if !literal.is_empty() {
+                            segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                        }
+                        segments.push(Segment::FieldCode(code));
+                    }
+                    None => return Err(ExecError::DanglingPercent),
+                }
+            }
+            other => {
+                in_arg = true;
+                literal.push(other);
+            }
+        }
+    }
+    if in_arg {
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        args.push(segments);
+    }
+    Ok(args)
+}
+
+/// Turns an Exec value into the argv to spawn. We never pass files or URLs, so `%f`, `%F`, `%u`
+/// and `%U` expand to nothing, as do the deprecated codes.
+pub fn expand(exec: &str, context: &ExecContext) -> Result<Vec<String>, ExecError> {
+    let mut argv = Vec::new();
+    for segments in tokenize(exec)? {
+        if let [Segment::FieldCode(code)] = segments.as_slice() {
+            match code {
+                'i' => {
+                    if let Some(icon) = context.icon {
+                        argv.push("--icon".to_string());
+                        argv.push(icon.to_string());
+                    }
+                    continue;
+                }
+                'f' | 'F' | 'u' | 'U' | 'd' | 'D' | 'n' | 'N' | 'v' | 'm' => continue,
+                _ => {}
+            }
+        }
+
+        let mut arg = String::new();
+        for segment in segments {
+            match segment {
+                Segment::Literal(text) => arg.push_str(&text),
+                Segment::FieldCode('c') => arg.push_str(context.name),
+                Segment::FieldCode('k') => arg.push_str(&context.desktop_file.to_string_lossy()),
+                Segment::FieldCode('i') => arg.push_str(context.icon.unwrap_or_default()),
+                Segment::FieldCode('f' | 'F' | 'u' | 'U' | 'd' | 'D' | 'n' | 'N' | 'v' | 'm') => {}
+                Segment::FieldCode(other) => return Err(ExecError::InvalidFieldCode(other)),
+            }
+        }
+        argv.push(arg);
+    }
+
+    if argv.is_empty() {
+        return Err(ExecError::Empty);
+    }
+    Ok(argv)
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+/// Resolves `program` against `$PATH` like a shell would, unless it already contains a `/`.
+pub fn find_executable(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return is_executable(&path).then_some(path);
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_plain(exec: &str) -> Result<Vec<String>, ExecError> {
+        expand(exec, &ExecContext { icon: Some("icon"), name: "Name", desktop_file: Path::new("/app.desktop") })
+    }
+
+    #[test]
+    fn double_percent_is_literal_inside_quotes() {
+        assert_eq!(expand_plain(r#"sh -c "printf 100%%""#).unwrap(), ["sh", "-c", "printf 100%"]);
+        assert_eq!(expand_plain(r#"sh -c "echo %f %%%%""#).unwrap(), ["sh", "-c", "echo %f %%"]);
+    }
+
+    #[test]
+    fn expands_field_codes_outside_quotes() {
+        assert_eq!(expand_plain("app 100%% %U --class=%c %i %k").unwrap(),
+            ["app", "100%", "--class=Name", "--icon", "icon", "/app.desktop"]);
+        assert!(matches!(expand_plain("app %x"), Err(ExecError::InvalidFieldCode('x'))));
+        assert!(matches!(expand_plain("app %"), Err(ExecError::DanglingPercent)));
+    }
+}
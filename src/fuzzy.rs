@@ -0,0 +1,109 @@
+const SCORE_MATCH: i64 = 16;
+const BONUS_PREFIX: i64 = 12;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL_CASE: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 5;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 1;
+
+#[derive(Clone, Debug, Default)]
+pub struct Match {
+    pub score: i64,
+    /// Char indices of `text` that matched, sorted.
+    pub positions: Vec<usize>,
+}
+
+/// Bonus for a match at `index`: start of the text, start of a word, or a camelCase hump.
+fn position_bonus(text: &[char], index: usize) -> i64 {
+    if index == 0 {
+        return BONUS_PREFIX;
+    }
+    let (prev, current) = (text[index - 1], text[index]);
+    if !prev.is_alphanumeric() && current.is_alphanumeric() {
+        BONUS_BOUNDARY
+    } else if prev.is_lowercase() && current.is_uppercase() {
+        BONUS_CAMEL_CASE
+    } else {
+        0
+    }
+}
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Best subsequence alignment of `pattern` in `text`, ignoring case, or `None` when `pattern`
+/// is not a subsequence. This is the usual Smith-Waterman style DP also used by fzf and skim.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<Match> {
+    let pattern: Vec<char> = pattern.chars().map(lowercase).collect();
+    let original: Vec<char> = text.chars().collect();
+    let text: Vec<char> = original.iter().copied().map(lowercase).collect();
+    let (n, m) = (pattern.len(), text.len());
+    if n == 0 {
+        return Some(Match::default());
+    }
+    if n > m {
+        return None;
+    }
+
+    // scores[i][j]: best score with pattern[i] matched at text[j]; from[i][j]: where pattern[i - 1] went
+    let mut scores = vec![vec![None::<i64>; m]; n];
+    let mut from = vec![vec![0usize; m]; n];
+    for i in 0..n {
+        // Best score[i - 1][k] for k < j - 1, minus the penalty for the gap up to j
+        let mut gapped: Option<(i64, usize)> = None;
+        for j in i..m {
+            if i > 0 && j >= 2 {
+                gapped = gapped.map(|(score, k)| (score - PENALTY_GAP_EXTENSION, k));
+                if let Some(score) = scores[i - 1][j - 2] {
+                    let candidate = score - PENALTY_GAP_START;
+                    if gapped.is_none_or(|(best, _)| candidate > best) {
+                        gapped = Some((candidate, j - 2));
+                    }
+                }
+            }
+            if text[j] != pattern[i] {
+                continue;
+            }
+            let base = SCORE_MATCH + position_bonus(&original, j);
+            if i == 0 {
+                scores[i][j] = Some(base);
+                continue;
+            }
+            let consecutive = scores[i - 1][j - 1].map(|score| (score + BONUS_CONSECUTIVE, j - 1));
+            let best = match (consecutive, gapped) {
+                (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+                (a, b) => a.or(b),
+            };
+            if let Some((score, k)) = best {
+                scores[i][j] = Some(base + score);
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let (mut j, score) = scores[n - 1].iter()
+        .enumerate()
+        .filter_map(|(j, score)| score.map(|score| (j, score)))
+        .max_by_key(|&(j, score)| (score, std::cmp::Reverse(j)))?;
+    let mut positions = vec![0; n];
+    for i in (0..n).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+    Some(Match { score, positions })
+}
+
+/// Matches every whitespace separated token of `query` against `text` on its own, so
+/// "lo wri" finds "LibreOffice Writer". Scores add up and positions are merged.
+pub fn match_query(query: &str, text: &str) -> Option<Match> {
+    let mut combined = Match::default();
+    for token in query.split_whitespace() {
+        let token_match = fuzzy_match(token, text)?;
+        combined.score += token_match.score;
+        combined.positions.extend(token_match.positions);
+    }
+    combined.positions.sort_unstable();
+    combined.positions.dedup();
+    Some(combined)
+}
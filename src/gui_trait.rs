@@ -1,16 +1,120 @@
 use std::error::Error;
+use std::sync::{Arc, Mutex};
+use clap::ValueEnum;
+use crate::eframe_impl::EframeGui;
+use crate::power::PowerAction;
+
+/// Lets another thread focus or close a window that is being shown.
+pub trait WindowHandle: Send {
+    fn focus(&self);
+    fn close(&self);
+    /// Redraws the window, e.g. to pick up a reloaded config.
+    fn refresh(&self);
+}
+
+/// Filled by the frontend with a handle to its window for as long as the window is open.
+pub type WindowSlot = Arc<Mutex<Option<Box<dyn WindowHandle>>>>;
 
 pub trait GuiFramework {
-    fn run(app: Box<dyn AppInterface>) -> Result<(), Box<dyn Error>>;
+    fn run(app: Box<dyn AppInterface>, window: WindowSlot) -> Result<(), Box<dyn Error>>;
+}
+
+/// The frontends `--backend` chooses from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Backend {
+    #[default]
+    Eframe,
+}
+
+impl Backend {
+    pub fn run(self, app: Box<dyn AppInterface>, window: WindowSlot) -> Result<(), Box<dyn Error>> {
+        match self {
+            Backend::Eframe => EframeGui::run(app, window),
+        }
+    }
+}
+
+/// Ways the keyboard moves the selection through the results.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Movement {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    First,
+    Last,
+}
+
+/// Everything a frontend can tell the launcher. Rows are indices into `ViewModel::results`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LauncherEvent {
+    /// The search text was edited.
+    QueryChanged(String),
+    /// Launch the selected row, or confirm a pending power action.
+    Submit,
+    /// Launch `row`, e.g. when it was clicked.
+    Activate(usize),
+    /// Close without launching anything, or cancel a pending power action.
+    Cancel,
+    MoveSelection(Movement),
+    /// Show or hide the actions of the application in `row`.
+    ToggleActions(usize),
+    /// Power off, restart, log out, sleep or lock, after a confirmation if one is configured.
+    PowerAction(PowerAction),
+    ConfirmPower,
+    CancelPower,
+    /// Sent regularly while a power action is pending, to run its countdown.
+    Tick,
+    /// The frontend picked up a reloaded config.
+    ConfigChanged,
+    /// Sent by the launcher to itself once the power actions this system supports are known.
+    PowerActionsFound(Vec<PowerAction>),
+    /// Sent by the launcher to itself once a power action was carried out or failed.
+    PowerPerformed(Result<(), String>),
+}
+
+/// One row of the result list.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResultRow {
+    pub label: String,
+    /// Char indices of `label` matched by the query.
+    pub highlights: Vec<usize>,
+    /// Shown on hover.
+    pub description: Option<String>,
+    /// The field that matched when it is not the name, e.g. "keywords".
+    pub matched_field: Option<String>,
+    /// Whether this is an application with actions that can be shown below it.
+    pub has_actions: bool,
+    pub expanded: bool,
+}
+
+/// A power action waiting for confirmation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PowerPrompt {
+    pub action: PowerAction,
+    /// Until it goes ahead on its own, rounded up.
+    pub seconds_left: u64,
+}
+
+/// What a frontend draws.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ViewModel {
+    pub query: String,
+    pub results: Vec<ResultRow>,
+    /// The row `Submit` launches.
+    pub selected: Option<usize>,
+    pub time: String,
+    /// Why the config file was rejected; the previous settings stay active meanwhile.
+    pub config_error: Option<String>,
+    pub power_prompt: Option<PowerPrompt>,
+    /// Power actions to offer, in display order.
+    pub power_actions: Vec<PowerAction>,
+    /// Work is running in the background; keep sending `Tick` so its result shows up.
+    pub busy: bool,
+    pub should_quit: bool,
 }
 
 pub trait AppInterface {
-    fn update(&mut self);
-    fn render(&self) -> String;
-    fn handle_input(&mut self, input: &str);
-    fn should_quit(&self) -> bool;
-    fn get_query(&self) -> String;
-    fn get_search_results(&self) -> Vec<String>;
-    fn get_time(&self) -> String;
-    fn launch_app(&mut self, app_name: &str);
-}
\ No newline at end of file
+    fn handle_event(&mut self, event: LauncherEvent);
+    fn view(&self) -> ViewModel;
+}
@@ -0,0 +1,199 @@
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use serde::{Serialize, Deserialize};
+use bincode::Options;
+use rayon::prelude::*;
+use once_cell::sync::Lazy;
+use crate::applications::{application_dirs, desktop_file_id, parse_desktop_entry, Application, DEBUG};
+use crate::cache::{write_atomically, XDG_DIRS};
+
+/// Bump whenever `Application` or the index layout changes.
+const INDEX_VERSION: u32 = 2;
+
+/// `$XDG_CACHE_HOME/rustrocket/index.bin`
+static INDEX_FILE: Lazy<PathBuf> = Lazy::new(|| XDG_DIRS.get_cache_home().join("index.bin"));
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct IndexedFile {
+    id: String,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// `None` for files that are not listed: hidden, excluded or unparsable. They are kept so
+    /// that they still mask entries with the same ID in lower-precedence directories.
+    app: Option<Application>,
+}
+
+/// Everything found below one `applications` directory.
+#[derive(Clone, Serialize, Deserialize)]
+struct DirIndex {
+    root: PathBuf,
+    /// The root and every subdirectory with its mtime; `None` if the root does not exist.
+    dirs: Vec<(PathBuf, Option<SystemTime>)>,
+    files: Vec<IndexedFile>,
+}
+
+impl DirIndex {
+    /// Walks `root`, parsing only files whose mtime differs from `previous`.
+    fn scan(root: &Path, previous: Option<&DirIndex>) -> Self {
+        let mut index = DirIndex { root: root.to_path_buf(), dirs: Vec::new(), files: Vec::new() };
+        let mut paths = Vec::new();
+        index.walk(root, &mut paths);
+
+        index.files = paths.into_par_iter()
+            .filter_map(|path| {
+                let id = desktop_file_id(root, &path)?;
+                let modified = modified(&path);
+                let unchanged = previous.and_then(|previous| {
+                    previous.files.iter().find(|file| file.path == path && file.modified == modified)
+                });
+                Some(match unchanged {
+                    Some(file) => file.clone(),
+                    None => {
+                        let app = parse_desktop_entry(&id, &path);
+                        IndexedFile { id, path, modified, app }
+                    }
+                })
+            })
+            .collect();
+        index
+    }
+
+    fn walk(&mut self, dir: &Path, paths: &mut Vec<PathBuf>) {
+        self.dirs.push((dir.to_path_buf(), modified(dir)));
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+            if path.is_dir() {
+                self.walk(&path, paths);
+            } else if path.extension().is_some_and(|ext| ext == "desktop") {
+                paths.push(path);
+            }
+        }
+    }
+
+    /// Files being added, removed or replaced bump their directory's mtime; files edited in
+    /// place only bump their own.
+    fn is_fresh(&self) -> bool {
+        self.dirs.iter().all(|(dir, mtime)| modified(dir) == *mtime)
+            && self.files.iter().all(|file| modified(&file.path) == file.modified)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Index {
+    version: u32,
+    /// Parsing depends on the locale and the current desktop.
+    environment: Vec<Option<String>>,
+    dirs: Vec<DirIndex>,
+}
+
+fn environment() -> Vec<Option<String>> {
+    ["LC_ALL", "LC_MESSAGES", "LANG", "XDG_CURRENT_DESKTOP"].iter()
+        .map(|var| env::var(var).ok())
+        .chain(std::iter::once(Some(env!("CARGO_PKG_VERSION").to_string())))
+        .collect()
+}
+
+fn read_index() -> Option<Index> {
+    let data = fs::read(&*INDEX_FILE).ok()?;
+    let index: Index = bincode::DefaultOptions::new().with_fixint_encoding().deserialize(&data).ok()?;
+    (index.version == INDEX_VERSION && index.environment == environment()).then_some(index)
+}
+
+fn write_index(index: &Index) -> Result<(), Box<dyn std::error::Error>> {
+    let data = bincode::DefaultOptions::new().with_fixint_encoding().serialize(index)?;
+    write_atomically(&INDEX_FILE, &data)?;
+    Ok(())
+}
+
+/// All listed applications. Directories whose mtimes match the cached index are taken from it,
+/// the others are rescanned, reparsing only the files that changed. With `RUSTROCKET_DEBUG` every
+/// file is reparsed, so that the reasons for leaving entries out are logged.
+pub fn load_applications() -> Vec<Application> {
+    let cached = if *DEBUG { None } else { read_index() };
+    let mut changed = cached.is_none();
+    let dirs: Vec<DirIndex> = application_dirs().iter()
+        .map(|root| {
+            let previous = cached.as_ref().and_then(|index| index.dirs.iter().find(|dir| dir.root == *root));
+            match previous {
+                Some(previous) if previous.is_fresh() => previous.clone(),
+                _ => {
+                    changed = true;
+                    DirIndex::scan(root, previous)
+                }
+            }
+        })
+        .collect();
+
+    let index = Index { version: INDEX_VERSION, environment: environment(), dirs };
+    if changed {
+        if let Err(err) = write_index(&index) {
+            eprintln!("Failed to write {}: {}", INDEX_FILE.display(), err);
+        }
+    }
+
+    listed(index.dirs)
+}
+
+/// The applications listed from `dirs`, in order of decreasing precedence. When several
+/// directories provide the same ID only the one from the highest-precedence directory is kept,
+/// so user overrides (including `Hidden=true` ones) mask the system entry.
+fn listed(dirs: Vec<DirIndex>) -> Vec<Application> {
+    let mut seen = HashSet::new();
+    dirs.into_iter()
+        .flat_map(|dir| dir.files)
+        .filter(|file| seen.insert(file.id.clone()))
+        .filter_map(|file| file.app)
+        .filter(Application::is_installed)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory under the temporary directory.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rustrocket-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_entry(path: &Path, name: &str, extra: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, format!("[Desktop Entry]\nType=Application\nName={}\nExec=true\n{}", name, extra)).unwrap();
+    }
+
+    #[test]
+    fn higher_precedence_directories_win() {
+        let root = temp_dir("precedence");
+        let (user, system) = (root.join("user"), root.join("system"));
+        write_entry(&system.join("kde/foo.desktop"), "System Foo", "");
+        write_entry(&user.join("kde-foo.desktop"), "User Foo", "");
+        write_entry(&system.join("editor.desktop"), "Editor", "");
+        write_entry(&user.join("editor.desktop"), "Hidden Editor", "Hidden=true\n");
+        write_entry(&system.join("files.desktop"), "Files", "");
+
+        let system_index = DirIndex::scan(&system, None);
+        let mut ids: Vec<&str> = system_index.files.iter().map(|file| file.id.as_str()).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, ["editor.desktop", "files.desktop", "kde-foo.desktop"]);
+
+        let apps = listed(vec![DirIndex::scan(&user, None), system_index]);
+        let mut names: Vec<(&str, &str)> = apps.iter().map(|app| (app.id.as_str(), app.name.as_str())).collect();
+        names.sort_unstable();
+        // The user's Hidden=true editor masks the system one
+        assert_eq!(names, [("files.desktop", "Files"), ("kde-foo.desktop", "User Foo")]);
+    }
+}
@@ -0,0 +1,153 @@
+//! Control socket of a running launcher.
+//!
+//! Every launcher with a window, and the `--daemon`, listens on
+//! `$XDG_RUNTIME_DIR/rustrocket/rustrocket.sock`. A client connects, writes one request line and
+//! reads one response line:
+//!
+//! ```text
+//! request:  RUSTROCKET <protocol version> <PING|SHOW|HIDE|TOGGLE>\n
+//! response: OK\n
+//!           ERR <message>\n
+//! ```
+//!
+//! The protocol version is currently 1. A server answers requests carrying any other version
+//! with `ERR unsupported protocol version <version>`, so new commands or arguments must come
+//! with a version bump.
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+use crate::cache::XDG_DIRS;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+const MAGIC: &str = "RUSTROCKET";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Request {
+    Ping,
+    Show,
+    Hide,
+    Toggle,
+}
+
+impl Request {
+    fn as_str(self) -> &'static str {
+        match self {
+            Request::Ping => "PING",
+            Request::Show => "SHOW",
+            Request::Hide => "HIDE",
+            Request::Toggle => "TOGGLE",
+        }
+    }
+
+    fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        if words.next() != Some(MAGIC) {
+            return Err("not a RustRocket request".to_string());
+        }
+        let version = words.next().ok_or("missing protocol version")?;
+        if version.parse() != Ok(PROTOCOL_VERSION) {
+            return Err(format!("unsupported protocol version {}", version));
+        }
+        let request = match words.next() {
+            Some("PING") => Request::Ping,
+            Some("SHOW") => Request::Show,
+            Some("HIDE") => Request::Hide,
+            Some("TOGGLE") => Request::Toggle,
+            Some(other) => return Err(format!("unknown command {}", other)),
+            None => return Err("missing command".to_string()),
+        };
+        match words.next() {
+            Some(extra) => Err(format!("unexpected argument {}", extra)),
+            None => Ok(request),
+        }
+    }
+}
+
+fn socket_path() -> io::Result<PathBuf> {
+    XDG_DIRS.place_runtime_file("rustrocket.sock")
+}
+
+/// Sends `request` to the running instance. `Ok(None)` means no instance is listening,
+/// `Ok(Some(Err(..)))` that it refused the request.
+pub fn send(request: Request) -> io::Result<Option<Result<(), String>>> {
+    let mut stream = match UnixStream::connect(socket_path()?) {
+        Ok(stream) => stream,
+        Err(err) if matches!(err.kind(), io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    writeln!(stream, "{} {} {}", MAGIC, PROTOCOL_VERSION, request.as_str())?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    let response = response.trim_end();
+    Ok(Some(match response.strip_prefix("ERR ") {
+        Some(message) => Err(message.to_string()),
+        None if response == "OK" => Ok(()),
+        None => Err(format!("malformed response {:?}", response)),
+    }))
+}
+
+/// Removes the socket when dropped.
+pub struct Server {
+    path: PathBuf,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Binds the control socket and answers requests with `handler` on a background thread.
+/// Returns `Ok(None)` when another instance already owns the socket.
+pub fn serve<F>(handler: F) -> io::Result<Option<Server>>
+where
+    F: Fn(Request) -> Result<(), String> + Send + 'static,
+{
+    let path = socket_path()?;
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) if err.kind() == io::ErrorKind::AddrInUse => {
+            if UnixStream::connect(&path).is_ok() {
+                return Ok(None);
+            }
+            // Left behind by an instance that did not shut down cleanly
+            fs::remove_file(&path)?;
+            UnixListener::bind(&path)?
+        }
+        Err(err) => return Err(err),
+    };
+
+    thread::spawn(move || {
+        for stream in listener.incoming().filter_map(Result::ok) {
+            if let Err(err) = answer(stream, &handler) {
+                eprintln!("IPC error: {}", err);
+            }
+        }
+    });
+    Ok(Some(Server { path }))
+}
+
+fn answer<F>(stream: UnixStream, handler: &F) -> io::Result<()>
+where
+    F: Fn(Request) -> Result<(), String>,
+{
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut line = String::new();
+    if BufReader::new(&stream).read_line(&mut line)? == 0 {
+        // Another instance probing whether we are alive
+        return Ok(());
+    }
+    let mut stream = stream;
+    match Request::parse(&line).and_then(handler) {
+        Ok(()) => writeln!(stream, "OK"),
+        Err(message) => writeln!(stream, "ERR {}", message),
+    }
+}
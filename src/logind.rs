@@ -0,0 +1,106 @@
+//! Power actions through systemd-logind's `org.freedesktop.login1.Manager` on the system bus.
+//! Unlike the commands, it knows whether an action is allowed for this session and asks polkit
+//! for authorization when needed.
+
+use zbus::{
+    blocking::{Connection, Proxy},
+    fdo,
+};
+use crate::power::PowerAction;
+
+pub const DESTINATION: &str = "org.freedesktop.login1";
+pub const PATH: &str = "/org/freedesktop/login1";
+const INTERFACE: &str = "org.freedesktop.login1.Manager";
+
+/// Errors meaning logind never saw the request, as opposed to it refusing it.
+const UNREACHABLE_ERRORS: [&str; 2] = [
+    "org.freedesktop.DBus.Error.ServiceUnknown",
+    "org.freedesktop.DBus.Error.NameHasNoOwner",
+];
+
+pub struct Logind {
+    proxy: Proxy<'static>,
+}
+
+/// The `Can*` query and the method for `action`, `None` for those logind has no method for.
+fn methods(action: PowerAction) -> Option<(&'static str, &'static str)> {
+    match action {
+        PowerAction::PowerOff => Some(("CanPowerOff", "PowerOff")),
+        PowerAction::Reboot => Some(("CanReboot", "Reboot")),
+        PowerAction::Suspend => Some(("CanSuspend", "Suspend")),
+        PowerAction::Hibernate => Some(("CanHibernate", "Hibernate")),
+        PowerAction::HybridSleep => Some(("CanHybridSleep", "HybridSleep")),
+        PowerAction::SuspendThenHibernate => Some(("CanSuspendThenHibernate", "SuspendThenHibernate")),
+        // Both act on a session rather than the machine
+        PowerAction::Logout | PowerAction::Lock => None,
+    }
+}
+
+impl Logind {
+    /// Logind on the system bus.
+    pub fn connect() -> zbus::Result<Self> {
+        Self::new(Connection::system()?)
+    }
+
+    /// Logind, or whatever owns its name, on `connection`'s bus.
+    pub fn new(connection: Connection) -> zbus::Result<Self> {
+        let proxy = Proxy::new_owned(connection, DESTINATION, PATH, INTERFACE)?;
+        Ok(Self { proxy })
+    }
+
+    pub fn handles(action: PowerAction) -> bool {
+        methods(action).is_some()
+    }
+
+    /// Whether logind allows `action`. "challenge" counts as allowed since polkit asks for
+    /// authorization when the action is performed.
+    pub fn can(&self, action: PowerAction) -> zbus::Result<bool> {
+        let query = match methods(action) {
+            Some((query, _)) => query,
+            None => return Ok(false),
+        };
+        let answer: String = self.proxy.call(query, &())?;
+        Ok(answer == "yes" || answer == "challenge")
+    }
+
+    pub fn perform(&self, action: PowerAction) -> zbus::Result<()> {
+        let method = match methods(action) {
+            Some((_, method)) => method,
+            None => return Err(zbus::Error::Unsupported),
+        };
+        // interactive: let polkit ask for a password instead of failing outright
+        self.proxy.call::<_, _, ()>(method, &(true,))
+    }
+}
+
+/// Whether `err` proves logind never saw the call, so falling back to another way of performing
+/// the action cannot do it twice. Anything else, in particular a denied or cancelled
+/// authorization or a connection lost halfway through the call, counts as logind's answer.
+pub fn is_unreachable(err: &zbus::Error) -> bool {
+    match err {
+        zbus::Error::Connection(..) | zbus::Error::Address(_) | zbus::Error::Handshake(_) => true,
+        zbus::Error::MethodError(name, _, _) => UNREACHABLE_ERRORS.contains(&name.as_str()),
+        zbus::Error::FDO(err) => matches!(**err, fdo::Error::ServiceUnknown(_) | fdo::Error::NameHasNoOwner(_)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io, sync::Arc};
+
+    #[test]
+    fn only_errors_before_the_call_are_unreachable() {
+        let fdo_error = |err| zbus::Error::FDO(Box::new(err));
+        assert!(is_unreachable(&zbus::Error::Handshake("refused".to_string())));
+        assert!(is_unreachable(&fdo_error(fdo::Error::ServiceUnknown(String::new()))));
+        assert!(is_unreachable(&fdo_error(fdo::Error::NameHasNoOwner(String::new()))));
+
+        // The call may have arrived before the connection broke
+        let broken = io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe");
+        assert!(!is_unreachable(&zbus::Error::InputOutput(Arc::new(broken))));
+        assert!(!is_unreachable(&fdo_error(fdo::Error::UnknownMethod(String::new()))));
+        assert!(!is_unreachable(&fdo_error(fdo::Error::AccessDenied(String::new()))));
+    }
+}
@@ -1,15 +1,23 @@
 mod clock;
 mod power;
+mod logind;
 mod cache;
+mod config;
+mod desktop_entry;
+mod exec;
+mod fuzzy;
+mod applications;
+mod index;
+mod watch;
 mod app_launcher;
+mod terminal;
 mod gui_trait;
 mod eframe_impl;
+mod ipc;
+mod daemon;
+mod modes;
+mod cli;
 
-use gui_trait::GuiFramework;
-use eframe_impl::EframeGui;
-use app_launcher::AppLauncher;
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let app = Box::new(AppLauncher::default());
-    EframeGui::run(app)
-}
\ No newline at end of file
+fn main() -> std::process::ExitCode {
+    cli::main()
+}
@@ -0,0 +1,72 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    io::{self, BufRead},
+};
+use clap::ValueEnum;
+use crate::applications::Application;
+use crate::exec::find_executable;
+use crate::index::load_applications;
+use crate::power::PowerAction;
+
+/// What the launcher lists and what picking an entry does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Mode {
+    /// Desktop applications
+    #[default]
+    Drun,
+    /// Executables on $PATH
+    Run,
+    /// Lines read from stdin; the picked one is printed to stdout
+    Dmenu,
+    /// Power, sleep and lock actions
+    Power,
+}
+
+impl Mode {
+    /// The entries to list. Everything but desktop applications is presented as a bare
+    /// `Application`; power entries carry the `PowerAction` ID.
+    pub fn load_items(self) -> io::Result<Vec<Application>> {
+        Ok(match self {
+            Mode::Drun => load_applications(),
+            Mode::Run => executables(),
+            Mode::Dmenu => io::stdin().lock().lines()
+                .map(|line| line.map(|line| item(&line, &line, Vec::new())))
+                .collect::<io::Result<Vec<_>>>()?
+                .into_iter()
+                .filter(|item| !item.name.is_empty())
+                .collect(),
+            Mode::Power => PowerAction::available().into_iter().map(|action| item(action.id(), action.label(), Vec::new())).collect(),
+        })
+    }
+}
+
+fn item(id: &str, name: &str, exec: Vec<String>) -> Application {
+    Application {
+        id: id.to_string(),
+        name: name.to_string(),
+        untranslated_name: name.to_string(),
+        exec,
+        ..Application::default()
+    }
+}
+
+/// Every executable on `$PATH` by name, the first one found winning like in a shell.
+fn executables() -> Vec<Application> {
+    let mut found = BTreeMap::new();
+    let path = env::var_os("PATH").unwrap_or_default();
+    for dir in env::split_paths(&path) {
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for name in entries.filter_map(Result::ok).filter_map(|entry| entry.file_name().into_string().ok()) {
+            if !found.contains_key(&name) && find_executable(&dir.join(&name).to_string_lossy()).is_some() {
+                found.insert(name.clone(), dir.join(&name));
+            }
+        }
+    }
+    found.into_iter()
+        .map(|(name, path)| item(&name, &name, vec![path.to_string_lossy().into_owned()]))
+        .collect()
+}
@@ -1,63 +1,448 @@
-use std::process::Command;
-use std::path::Path;
+use std::{
+    fs,
+    process::Command,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use clap::ValueEnum;
+use once_cell::sync::Lazy;
+use crate::config::{config, Config};
+use crate::exec::find_executable;
+use crate::logind::{is_unreachable, Logind};
 
-fn execute_command(command: &str, args: &[&str]) -> Result<(), String> {
-    Command::new(command)
+/// Actions offered next to the results and by `RustRocket power`. Their names on the command
+/// line are their IDs.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum PowerAction {
+    #[value(name = "poweroff")]
+    PowerOff,
+    Reboot,
+    Logout,
+    Suspend,
+    Hibernate,
+    HybridSleep,
+    SuspendThenHibernate,
+    Lock,
+}
+
+impl PowerAction {
+    pub const ALL: [PowerAction; 8] = [
+        PowerAction::Lock,
+        PowerAction::Suspend,
+        PowerAction::Hibernate,
+        PowerAction::HybridSleep,
+        PowerAction::SuspendThenHibernate,
+        PowerAction::Logout,
+        PowerAction::Reboot,
+        PowerAction::PowerOff,
+    ];
+
+    /// Named after the matching `systemctl` verb where there is one.
+    pub fn id(self) -> &'static str {
+        match self {
+            PowerAction::PowerOff => "poweroff",
+            PowerAction::Reboot => "reboot",
+            PowerAction::Logout => "logout",
+            PowerAction::Suspend => "suspend",
+            PowerAction::Hibernate => "hibernate",
+            PowerAction::HybridSleep => "hybrid-sleep",
+            PowerAction::SuspendThenHibernate => "suspend-then-hibernate",
+            PowerAction::Lock => "lock",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|action| action.id() == id)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PowerAction::PowerOff => "Power off",
+            PowerAction::Reboot => "Restart",
+            PowerAction::Logout => "Log out",
+            PowerAction::Suspend => "Suspend",
+            PowerAction::Hibernate => "Hibernate",
+            PowerAction::HybridSleep => "Hybrid sleep",
+            PowerAction::SuspendThenHibernate => "Suspend, then hibernate",
+            PowerAction::Lock => "Lock",
+        }
+    }
+
+    /// How long the action waits for confirmation; zero when it does not ask.
+    pub fn countdown(self) -> Duration {
+        let countdown = &config().power.countdown;
+        Duration::from_secs(match self {
+            PowerAction::PowerOff => countdown.poweroff,
+            PowerAction::Reboot => countdown.reboot,
+            PowerAction::Logout => countdown.logout,
+            PowerAction::Suspend => countdown.suspend,
+            PowerAction::Hibernate => countdown.hibernate,
+            PowerAction::HybridSleep => countdown.hybrid_sleep,
+            PowerAction::SuspendThenHibernate => countdown.suspend_then_hibernate,
+            PowerAction::Lock => countdown.lock,
+        })
+    }
+
+    fn commands(self, config: &Config) -> &[Vec<String>] {
+        let power = &config.power;
+        match self {
+            PowerAction::PowerOff => &power.poweroff,
+            PowerAction::Reboot => &power.reboot,
+            PowerAction::Logout => &power.logout,
+            PowerAction::Suspend => &power.suspend,
+            PowerAction::Hibernate => &power.hibernate,
+            PowerAction::HybridSleep => &power.hybrid_sleep,
+            PowerAction::SuspendThenHibernate => &power.suspend_then_hibernate,
+            PowerAction::Lock => &power.lock,
+        }
+    }
+
+    /// Whether logind allows the action or, where logind is unused or cannot tell, whether the
+    /// kernel supports the sleep state it needs, as reported in `/sys/power`, and one of its
+    /// commands is installed.
+    fn is_available(self, config: &Config, logind: Option<&Logind>) -> bool {
+        if let Some(logind) = logind.filter(|_| Logind::handles(self)) {
+            match logind.can(self) {
+                Ok(allowed) => return allowed,
+                Err(err) => eprintln!("Failed to ask logind about {}: {}", self.id(), err),
+            }
+        }
+        let states = fs::read_to_string("/sys/power/state").unwrap_or_default();
+        let has_state = |state: &str| states.split_whitespace().any(|supported| supported == state);
+        let supported = match self {
+            PowerAction::Suspend => has_state("mem") || has_state("freeze"),
+            PowerAction::Hibernate => has_state("disk"),
+            // Hibernation that suspends instead of powering off once the image is written
+            PowerAction::HybridSleep => has_state("disk") && fs::read_to_string("/sys/power/disk")
+                .is_ok_and(|modes| modes.split_whitespace().any(|mode| mode.trim_matches(|c| c == '[' || c == ']') == "suspend")),
+            PowerAction::SuspendThenHibernate => has_state("mem") && has_state("disk"),
+            PowerAction::PowerOff | PowerAction::Reboot | PowerAction::Logout | PowerAction::Lock => true,
+        };
+        supported && self.commands(config).iter().any(|argv| argv.first().is_some_and(|program| find_executable(program).is_some()))
+    }
+
+    /// The actions worth offering on this system, in display order. Asking logind takes a round
+    /// trip per action, so the answer is computed once per configuration and then reused.
+    pub fn available() -> Vec<PowerAction> {
+        let config = config();
+        let mut cached = AVAILABLE.lock().unwrap();
+        if let Some((computed_for, actions)) = cached.as_ref() {
+            if Arc::ptr_eq(computed_for, &config) {
+                return actions.clone();
+            }
+        }
+        let logind = logind(&config);
+        let actions: Vec<PowerAction> = Self::ALL.iter().copied().filter(|action| action.is_available(&config, logind)).collect();
+        *cached = Some((config, actions.clone()));
+        actions
+    }
+
+    /// Asks logind to perform the action, falling back to the first installed command
+    /// configured for it when logind cannot be reached. When logind refuses, e.g. because
+    /// authorization was denied or cancelled, that is the answer.
+    pub fn perform(self) -> Result<(), String> {
+        let config = config();
+        self.perform_with(&config, logind(&config))
+    }
+
+    fn perform_with(self, config: &Config, logind: Option<&Logind>) -> Result<(), String> {
+        if let Some(logind) = logind.filter(|_| Logind::handles(self)) {
+            match logind.perform(self) {
+                Ok(()) => return Ok(()),
+                Err(err) if is_unreachable(&err) => eprintln!("logind is unavailable ({}); trying the configured commands", err),
+                Err(err) => return Err(format!("Failed to {}: {}", self.label().to_lowercase(), err)),
+            }
+        }
+        if run_first_installed(self.commands(config)) {
+            Ok(())
+        } else {
+            Err(format!("Failed to {}: No known command available", self.label().to_lowercase()))
+        }
+    }
+}
+
+/// The actions `available` last computed and the configuration they were computed for.
+static AVAILABLE: Mutex<Option<(Arc<Config>, Vec<PowerAction>)>> = Mutex::new(None);
+
+/// Connected on first use and shared by the whole process. `None` without a system bus, as in
+/// containers or on systems without systemd.
+static LOGIND: Lazy<Option<Logind>> = Lazy::new(|| Logind::connect().ok());
+
+/// The logind connection, unless disabled in `config` or unavailable.
+fn logind(config: &Config) -> Option<&'static Logind> {
+    if !config.power.use_logind {
+        return None;
+    }
+    LOGIND.as_ref()
+}
+
+/// A power action waiting for confirmation. It goes ahead when confirmed or once its countdown
+/// runs out, unless cancelled first. Time is passed in, so the owner decides what "now" is.
+#[derive(Clone, Copy, Debug)]
+pub struct PendingPower {
+    pub action: PowerAction,
+    deadline: Instant,
+}
+
+impl PendingPower {
+    pub fn new(action: PowerAction, countdown: Duration, now: Instant) -> Self {
+        Self { action, deadline: now + countdown }
+    }
+
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.deadline.saturating_duration_since(now)
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.deadline
+    }
+}
+
+/// How long a command has to fail before it counts as started. Most exit right away, but a
+/// screen locker keeps running until the screen is unlocked.
+const COMMAND_GRACE: Duration = Duration::from_secs(1);
+
+/// Runs `command` and waits up to `COMMAND_GRACE` for it to fail.
+fn execute_command(command: &str, args: &[String]) -> Result<(), String> {
+    let mut child = Command::new(command)
         .args(args)
         .spawn()
         .map_err(|e| format!("Failed to execute {}: {}", command, e))?;
-    Ok(())
+    let deadline = Instant::now() + COMMAND_GRACE;
+    loop {
+        match child.try_wait().map_err(|e| format!("Failed to wait for {}: {}", command, e))? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => return Err(format!("{} failed: {}", command, status)),
+            None if Instant::now() >= deadline => {
+                // Reap it once it exits, a daemon would otherwise collect zombies
+                thread::spawn(move || child.wait());
+                return Ok(());
+            }
+            None => thread::sleep(Duration::from_millis(20)),
+        }
+    }
 }
 
-pub fn power_off() {
-    let commands = vec![
-        ("shutdown", vec!["-h", "now"]),
-        ("systemctl", vec!["poweroff"]),
-        ("poweroff", vec![]),
-        ("halt", vec![]),
-    ];
-    
-    for (cmd, args) in commands {
-        if Path::new(&format!("/usr/bin/{}", cmd)).exists() || Path::new(&format!("/bin/{}", cmd)).exists() {
-            if execute_command(cmd, &args).is_ok() {
-                return;
+/// Runs the installed commands of `commands` in order until one succeeds, see `PowerConfig`.
+fn run_first_installed(commands: &[Vec<String>]) -> bool {
+    for argv in commands {
+        if let Some((cmd, args)) = argv.split_first() {
+            if find_executable(cmd).is_none() {
+                continue;
+            }
+            match execute_command(cmd, args) {
+                Ok(()) => return true,
+                Err(err) => eprintln!("{}", err),
             }
         }
     }
-    eprintln!("Failed to power off: No known command available");
+    false
 }
 
-pub fn restart() {
-    let commands = vec![
-        ("reboot", vec![]),
-        ("systemctl", vec!["reboot"]),
-        ("shutdown", vec!["-r", "now"]),
-    ];
-    
-    for (cmd, args) in commands {
-        if Path::new(&format!("/usr/bin/{}", cmd)).exists() || Path::new(&format!("/bin/{}", cmd)).exists() {
-            if execute_command(cmd, &args).is_ok() {
-                return;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Stdio},
+        sync::Mutex,
+    };
+    use zbus::{blocking::{connection, Connection}, fdo, interface};
+    use crate::logind::{DESTINATION, PATH};
+
+    /// A private `dbus-daemon`, killed when dropped.
+    struct TestBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl TestBus {
+        /// `None`, after saying so, when `dbus-daemon` is not installed.
+        fn start(name: &str) -> Option<Self> {
+            if find_executable("dbus-daemon").is_none() {
+                eprintln!("dbus-daemon is not installed, skipping");
+                return None;
             }
+            let dir = std::env::temp_dir().join(format!("rustrocket-test-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let config = dir.join("bus.conf");
+            // Anyone may own any name and call anything
+            fs::write(&config, format!(
+                "<busconfig><type>session</type><listen>unix:path={}</listen><auth>EXTERNAL</auth>\
+                 <policy context=\"default\"><allow send_destination=\"*\" eavesdrop=\"true\"/><allow eavesdrop=\"true\"/><allow own=\"*\"/></policy></busconfig>",
+                dir.join("bus").display(),
+            )).unwrap();
+            let mut daemon = Command::new("dbus-daemon")
+                .arg(format!("--config-file={}", config.display()))
+                .args(["--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap();
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+            Some(Self { daemon, address: address.trim().to_string() })
+        }
+
+        /// Calls time out quickly, so a broken setup fails the test instead of hanging it.
+        fn connect(&self) -> Connection {
+            connection::Builder::address(self.address.as_str()).unwrap()
+                .method_timeout(Duration::from_secs(5))
+                .build()
+                .unwrap()
+        }
+
+        fn logind(&self) -> Logind {
+            Logind::new(self.connect()).unwrap()
         }
     }
-    eprintln!("Failed to restart: No known command available");
-}
 
-pub fn logout() {
-    let commands = vec![
-        ("swaymsg", vec!["exit"]),
-        ("gnome-session-quit", vec!["--logout", "--no-prompt"]),
-        ("kdeinit5", vec!["--logout"]),
-        ("logout", vec![]),
-    ];
-    
-    for (cmd, args) in commands {
-        if Path::new(&format!("/usr/bin/{}", cmd)).exists() || Path::new(&format!("/bin/{}", cmd)).exists() {
-            if execute_command(cmd, &args).is_ok() {
-                return;
+    impl Drop for TestBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    /// Methods performed, with their `interactive` argument.
+    type Performed = Arc<Mutex<Vec<(String, bool)>>>;
+
+    /// Answers every `Can*` with `can`, and performs actions unless `deny`, recording them.
+    struct MockManager {
+        can: &'static str,
+        deny: bool,
+        performed: Performed,
+    }
+
+    impl MockManager {
+        fn perform(&self, method: &str, interactive: bool) -> fdo::Result<()> {
+            if self.deny {
+                return Err(fdo::Error::AccessDenied("Interactive authentication required.".to_string()));
             }
+            self.performed.lock().unwrap().push((method.to_string(), interactive));
+            Ok(())
+        }
+    }
+
+    #[interface(name = "org.freedesktop.login1.Manager")]
+    impl MockManager {
+        fn can_power_off(&self) -> String {
+            self.can.to_string()
+        }
+
+        fn can_suspend(&self) -> String {
+            self.can.to_string()
+        }
+
+        fn power_off(&self, interactive: bool) -> fdo::Result<()> {
+            self.perform("PowerOff", interactive)
         }
+
+        fn suspend(&self, interactive: bool) -> fdo::Result<()> {
+            self.perform("Suspend", interactive)
+        }
+    }
+
+    /// Serves a `MockManager` as login1 on `bus` until dropped.
+    fn serve_mock(bus: &TestBus, can: &'static str, deny: bool) -> (Connection, Performed) {
+        let performed = Arc::new(Mutex::new(Vec::new()));
+        let manager = MockManager { can, deny, performed: performed.clone() };
+        let server = connection::Builder::address(bus.address.as_str()).unwrap()
+            .name(DESTINATION).unwrap()
+            .serve_at(PATH, manager).unwrap()
+            .build()
+            .unwrap();
+        (server, performed)
+    }
+
+    /// The defaults, but no command for poweroff is ever installed, so falling back to the
+    /// commands fails with "No known command available".
+    fn config_without_commands() -> Config {
+        let mut config = Config::default();
+        config.power.poweroff = vec![vec!["rustrocket-no-such-program".to_string()]];
+        config
+    }
+
+    #[test]
+    fn logind_decides_availability() {
+        let bus = match TestBus::start("logind-can") {
+            Some(bus) => bus,
+            None => return,
+        };
+        let config = config_without_commands();
+        let (server, _) = serve_mock(&bus, "challenge", false);
+        let logind = bus.logind();
+        assert!(PowerAction::PowerOff.is_available(&config, Some(&logind)), "challenge counts as allowed");
+        drop(server);
+
+        let (_server, _) = serve_mock(&bus, "na", false);
+        let logind = bus.logind();
+        assert!(!PowerAction::Suspend.is_available(&config, Some(&logind)));
+    }
+
+    #[test]
+    fn logind_performs_interactively() {
+        let bus = match TestBus::start("logind-perform") {
+            Some(bus) => bus,
+            None => return,
+        };
+        let (_server, performed) = serve_mock(&bus, "yes", false);
+        let logind = bus.logind();
+        assert_eq!(PowerAction::PowerOff.perform_with(&config_without_commands(), Some(&logind)), Ok(()));
+        assert_eq!(*performed.lock().unwrap(), [("PowerOff".to_string(), true)]);
+    }
+
+    #[test]
+    fn denied_authorization_does_not_fall_back() {
+        let bus = match TestBus::start("logind-denied") {
+            Some(bus) => bus,
+            None => return,
+        };
+        let (_server, performed) = serve_mock(&bus, "challenge", true);
+        let logind = bus.logind();
+        let err = PowerAction::PowerOff.perform_with(&config_without_commands(), Some(&logind)).unwrap_err();
+        assert!(err.contains("AccessDenied"), "{}", err);
+        assert!(performed.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn unreachable_logind_falls_back_to_commands() {
+        let bus = match TestBus::start("logind-missing") {
+            Some(bus) => bus,
+            None => return,
+        };
+        // Nobody owns the login1 name on this bus
+        let logind = bus.logind();
+        let config = config_without_commands();
+        assert_eq!(PowerAction::PowerOff.perform_with(&config, Some(&logind)), Err("Failed to power off: No known command available".to_string()));
+        assert!(!PowerAction::PowerOff.is_available(&config, Some(&logind)));
+    }
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn failing_command_falls_through() {
+        let dir = std::env::temp_dir().join(format!("rustrocket-test-{}-fallthrough", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let marker = dir.join("ran");
+        let commands = [
+            argv(&["sh", "-c", "exit 1"]),
+            argv(&["sh", "-c", &format!("touch '{}'", marker.display())]),
+        ];
+        assert!(run_first_installed(&commands));
+        assert!(marker.exists(), "the second command runs after the first fails");
+        assert!(!run_first_installed(&[argv(&["sh", "-c", "exit 1"])]));
+    }
+
+    #[test]
+    fn long_running_command_counts_as_started() {
+        let started = Instant::now();
+        assert!(run_first_installed(&[argv(&["sh", "-c", "sleep 5"])]));
+        assert!(started.elapsed() < Duration::from_secs(4), "does not wait for a screen locker to exit");
     }
-    eprintln!("Failed to logout: No known command available");
 }
@@ -1,12 +1,13 @@
 use qt_widgets::qt_core::{q_init_resource, QBox, QObject, QTimer, SlotNoArgs};
 use qt_widgets::qt_gui::QIcon;
 use qt_widgets::qt_widgets::{QApplication, QLineEdit, QListWidget, QMainWindow, QPushButton, QVBoxLayout, QWidget};
-use crate::gui_trait::{GuiFramework, AppInterface};
+use crate::gui_trait::{GuiFramework, AppInterface, LauncherEvent, WindowSlot};
+use crate::power::PowerAction;
 
 pub struct QtGui;
 
 impl GuiFramework for QtGui {
-    fn run(mut app: Box<dyn AppInterface>) -> Result<(), Box<dyn std::error::Error>> {
+    fn run(mut app: Box<dyn AppInterface>, _window: WindowSlot) -> Result<(), Box<dyn std::error::Error>> {
         QApplication::init(|_| {
             let mut window = QMainWindow::new_0a();
             window.set_window_title(&QString::from_std_str("Application Launcher"));
@@ -26,49 +27,56 @@ impl GuiFramework for QtGui {
             let time_label = QLabel::new();
             layout.add_widget(time_label.into_ptr());
 
-            let power_button = QPushButton::from_q_string(&QString::from_std_str("Power"));
-            let restart_button = QPushButton::from_q_string(&QString::from_std_str("Restart"));
-            let logout_button = QPushButton::from_q_string(&QString::from_std_str("Logout"));
-
             let button_layout = QHBoxLayout::new_0a();
-            button_layout.add_widget(power_button.into_ptr());
-            button_layout.add_widget(restart_button.into_ptr());
-            button_layout.add_widget(logout_button.into_ptr());
+            for action in PowerAction::available() {
+                let button = QPushButton::from_q_string(&QString::from_std_str(action.label()));
+                button.clicked().connect(&SlotNoArgs::new(move || {
+                    app.handle_event(LauncherEvent::PowerAction(action));
+                }));
+                button_layout.add_widget(button.into_ptr());
+            }
 
             layout.add_layout_1a(button_layout.into_ptr());
 
             let update_ui = SlotNoArgs::new(move || {
-                search_bar.set_text(&QString::from_std_str(&app.get_query()));
-                
+                app.handle_event(LauncherEvent::Tick);
+                let view = app.view();
+                search_bar.set_text(&QString::from_std_str(&view.query));
+
                 results_list.clear();
-                for result in app.get_search_results() {
-                    results_list.add_item_q_string(&QString::from_std_str(&result));
+                for result in &view.results {
+                    results_list.add_item_q_string(&QString::from_std_str(&result.label));
+                }
+                if let Some(row) = view.selected {
+                    results_list.set_current_row_1a(row as i32);
                 }
 
-                time_label.set_text(&QString::from_std_str(&app.get_time()));
+                let status = match view.power_prompt {
+                    Some(prompt) => format!("{} in {} s (Enter confirms, Esc cancels)", prompt.action.label(), prompt.seconds_left),
+                    None => view.time.clone(),
+                };
+                time_label.set_text(&QString::from_std_str(&status));
+                if view.should_quit {
+                    window.close();
+                }
             });
 
             search_bar.text_edited().connect(&SlotNoArgs::new(move || {
-                app.handle_input(&search_bar.text().to_std_string());
+                app.handle_event(LauncherEvent::QueryChanged(search_bar.text().to_std_string()));
                 update_ui.emit();
             }));
 
-            results_list.item_double_clicked().connect(&SlotNoArgs::new(move || {
-                if let Some(item) = results_list.current_item() {
-                    app.launch_app(&item.text().to_std_string());
-                }
-            }));
-
-            power_button.clicked().connect(&SlotNoArgs::new(move || {
-                app.handle_input("P");
-            }));
-
-            restart_button.clicked().connect(&SlotNoArgs::new(move || {
-                app.handle_input("R");
+            search_bar.return_pressed().connect(&SlotNoArgs::new(move || {
+                app.handle_event(LauncherEvent::Submit);
+                update_ui.emit();
             }));
 
-            logout_button.clicked().connect(&SlotNoArgs::new(move || {
-                app.handle_input("L");
+            results_list.item_double_clicked().connect(&SlotNoArgs::new(move || {
+                let row = results_list.current_row();
+                if row >= 0 {
+                    app.handle_event(LauncherEvent::Activate(row as usize));
+                    update_ui.emit();
+                }
             }));
 
             let timer = QTimer::new_1a(&window);
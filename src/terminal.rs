@@ -0,0 +1,46 @@
+use std::{env, path::Path};
+use crate::config::config;
+use crate::exec::find_executable;
+
+/// Terminals tried, in order, when neither the config nor `$TERMINAL` names one.
+const PROBED_TERMINALS: &[&str] = &["foot", "alacritty", "kitty", "wezterm"];
+
+/// The arguments a terminal expects between its own command line and the program to run.
+fn exec_separator(program: &str) -> &'static [&'static str] {
+    let name = Path::new(program).file_name().and_then(|name| name.to_str()).unwrap_or(program);
+    match name {
+        "xdg-terminal-exec" => &[],
+        "foot" | "kitty" | "gnome-terminal" | "kgx" | "ptyxis" => &["--"],
+        "wezterm" => &["start", "--"],
+        _ => &["-e"],
+    }
+}
+
+/// Builds the terminal part of the command line from a user supplied string such as `"foot"`
+/// or `"wezterm start"`. Extra arguments given by the user replace our own convention.
+fn from_command_line(command: &str) -> Option<Vec<String>> {
+    let mut words = command.split_whitespace().map(str::to_string);
+    let program = words.next()?;
+    find_executable(&program)?;
+    let extra: Vec<String> = words.collect();
+    let separator: Vec<String> = if extra.is_empty() {
+        exec_separator(&program).iter().map(|arg| arg.to_string()).collect()
+    } else {
+        extra
+    };
+    Some(std::iter::once(program).chain(separator).collect())
+}
+
+fn detect_terminal() -> Option<Vec<String>> {
+    config().terminal.as_deref().and_then(from_command_line)
+        .or_else(|| env::var("TERMINAL").ok().as_deref().and_then(from_command_line))
+        .or_else(|| from_command_line("xdg-terminal-exec"))
+        .or_else(|| PROBED_TERMINALS.iter().find_map(|terminal| from_command_line(terminal)))
+}
+
+/// Prefixes `argv` with a terminal emulator command line for `Terminal=true` entries.
+pub fn wrap_in_terminal(argv: &[String]) -> Result<Vec<String>, String> {
+    let mut wrapped = detect_terminal().ok_or("No terminal emulator found; set `terminal` in the config or $TERMINAL")?;
+    wrapped.extend_from_slice(argv);
+    Ok(wrapped)
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+use crate::applications::{application_dirs, Application};
+use crate::config::config_file_candidates;
+use crate::index::load_applications;
+
+/// How long the directories have to stay quiet before we rescan, so that a package
+/// transaction touching a hundred files costs a single rescan, and an editor saving through a
+/// temporary file a single reload.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// What changes an applications directory or any of its subdirectories.
+fn content_mask() -> WatchMask {
+    WatchMask::CREATE | WatchMask::DELETE | WatchMask::MOVED_FROM | WatchMask::MOVED_TO
+        | WatchMask::CLOSE_WRITE | WatchMask::ATTRIB | WatchMask::DELETE_SELF | WatchMask::MOVE_SELF
+}
+
+/// Enough to notice a missing applications directory (or one of its parents) being created.
+fn creation_mask() -> WatchMask {
+    WatchMask::CREATE | WatchMask::MOVED_TO | WatchMask::ONLYDIR
+}
+
+/// The nearest existing ancestor of a missing `path`, to notice it being created.
+fn creation_target(path: &Path) -> Option<(PathBuf, WatchMask)> {
+    path.ancestors().skip(1).find(|dir| dir.is_dir()).map(|ancestor| (ancestor.to_path_buf(), creation_mask()))
+}
+
+/// Every directory that has to be watched for applications: each existing applications
+/// directory with its subdirectories, and for a missing one its nearest existing ancestor.
+fn application_targets() -> Vec<(PathBuf, WatchMask)> {
+    let mut targets = Vec::new();
+    for root in application_dirs() {
+        if root.is_dir() {
+            collect_dirs(&root, &mut targets);
+        } else {
+            targets.extend(creation_target(&root));
+        }
+    }
+    targets
+}
+
+/// The directory of every file the config may be read from. Editors usually save by renaming
+/// a new file over the old one, which a watch on the file itself would not survive.
+fn config_targets() -> Vec<(PathBuf, WatchMask)> {
+    let mut targets = Vec::new();
+    for file in config_file_candidates() {
+        match file.parent() {
+            Some(dir) if dir.is_dir() => targets.push((dir.to_path_buf(), content_mask())),
+            Some(dir) => targets.extend(creation_target(dir)),
+            None => {}
+        }
+    }
+    targets
+}
+
+fn collect_dirs(dir: &Path, targets: &mut Vec<(PathBuf, WatchMask)>) {
+    targets.push((dir.to_path_buf(), content_mask()));
+    if let Ok(entries) = dir.read_dir() {
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+            if path.is_dir() {
+                collect_dirs(&path, targets);
+            }
+        }
+    }
+}
+
+struct Watcher {
+    inotify: Inotify,
+    targets: fn() -> Vec<(PathBuf, WatchMask)>,
+    watches: HashMap<PathBuf, WatchDescriptor>,
+}
+
+impl Watcher {
+    /// Brings the watches in line with `targets`. Directories that appeared get watched,
+    /// and ancestors watched for a directory that now exists are dropped.
+    fn update_watches(&mut self) {
+        let mut targets: HashMap<PathBuf, WatchMask> = HashMap::new();
+        for (dir, mask) in (self.targets)() {
+            *targets.entry(dir).or_insert(WatchMask::empty()) |= mask;
+        }
+
+        let stale: Vec<PathBuf> = self.watches.keys()
+            .filter(|dir| !targets.contains_key(*dir))
+            .cloned()
+            .collect();
+        for dir in stale {
+            if let Some(wd) = self.watches.remove(&dir) {
+                // Fails if the directory is gone, which already removed the watch
+                let _ = self.inotify.watches().remove(wd);
+            }
+        }
+        for (dir, mask) in targets {
+            // Adding again replaces the mask, e.g. once an ancestor is itself a watched directory
+            match self.inotify.watches().add(&dir, mask) {
+                Ok(wd) => {
+                    self.watches.insert(dir, wd);
+                }
+                Err(err) => eprintln!("Failed to watch {}: {}", dir.display(), err),
+            }
+        }
+    }
+
+    /// Blocks until something changed and then until things have been quiet for `DEBOUNCE`.
+    fn wait_for_changes(&mut self, buffer: &mut [u8]) -> io::Result<()> {
+        self.inotify.read_events_blocking(buffer)?;
+        loop {
+            thread::sleep(DEBOUNCE);
+            match self.inotify.read_events(buffer) {
+                Ok(_) => continue,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Calls `on_change` on a background thread whenever something below `targets` changed.
+fn watch<F>(what: &'static str, targets: fn() -> Vec<(PathBuf, WatchMask)>, on_change: F) -> io::Result<()>
+where
+    F: Fn() + Send + 'static,
+{
+    let mut watcher = Watcher { inotify: Inotify::init()?, targets, watches: HashMap::new() };
+    watcher.update_watches();
+
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            if let Err(err) = watcher.wait_for_changes(&mut buffer) {
+                eprintln!("Stopped watching {}: {}", what, err);
+                return;
+            }
+            watcher.update_watches();
+            on_change();
+        }
+    });
+    Ok(())
+}
+
+/// Watches every applications directory, including ones created later, and calls `on_change`
+/// with the reloaded application list after files were added, changed or removed. Reloading
+/// goes through the index, so only the files that changed are parsed again.
+pub fn watch_applications<F>(on_change: F) -> io::Result<()>
+where
+    F: Fn(Vec<Application>) + Send + 'static,
+{
+    watch("application directories", application_targets, move || on_change(load_applications()))
+}
+
+/// Calls `on_change` after the config file was created, changed or removed.
+pub fn watch_config<F>(on_change: F) -> io::Result<()>
+where
+    F: Fn() + Send + 'static,
+{
+    watch("the config file", config_targets, on_change)
+}